@@ -0,0 +1,257 @@
+//! Deserializing documents into a `Value` while deciding what
+//! happens when a mapping repeats a key
+use std::fmt;
+
+use serde::de::{DeserializeSeed, Deserializer, EnumAccess, Error, MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde_yaml::{value::{Tag, TaggedValue}, Mapping, Value};
+
+use transform::{key_name, timestamp};
+
+/// Deserializes any document into a `Value`. A repeated key
+/// replaces the earlier value, unless `strict` is set in which
+/// case it is an error
+#[derive(Clone, Copy)]
+pub struct Keys<'a> {
+    pub strict: bool,
+    /// The yaml being deserialized when timestamp values are
+    /// written as ISO-8601 UTC strings, see `Options::timestamps`
+    pub timestamps: Option<&'a str>,
+}
+
+impl<'a, 'de> DeserializeSeed<'de> for Keys<'a> {
+    type Value = Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'a, 'de> Visitor<'de> for Keys<'a> {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any YAML value")
+    }
+
+    fn visit_bool<E: Error>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Bool(b))
+    }
+
+    fn visit_i64<E: Error>(self, i: i64) -> Result<Value, E> {
+        Ok(Value::Number(i.into()))
+    }
+
+    fn visit_u64<E: Error>(self, u: u64) -> Result<Value, E> {
+        Ok(Value::Number(u.into()))
+    }
+
+    fn visit_f64<E: Error>(self, f: f64) -> Result<Value, E> {
+        Ok(Value::Number(f.into()))
+    }
+
+    fn visit_str<E: Error>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::String(s.to_string()))
+    }
+
+    fn visit_borrowed_str<E: Error>(self, s: &'de str) -> Result<Value, E> {
+        match self.timestamps.filter(|source| is_timestamp_scalar(source, s)).and_then(|_| timestamp(s)) {
+            Some(canonical) => Ok(Value::String(canonical)),
+            None => Ok(Value::String(s.to_string())),
+        }
+    }
+
+    fn visit_string<E: Error>(self, s: String) -> Result<Value, E> {
+        Ok(Value::String(s))
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_none<E: Error>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.deserialize(deserializer)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element_seed(self)? {
+            values.push(value);
+        }
+        Ok(Value::Sequence(values))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut mapping = Mapping::new();
+        loop {
+            // only values are timestamps
+            let seed = Key { keys: Keys { timestamps: None, ..self }, mapping: &mapping };
+            let key = match map.next_key_seed(seed)? {
+                Some(key) => key,
+                None => break,
+            };
+            let value = map.next_value_seed(self)?;
+            mapping.insert(key, value);
+        }
+        Ok(Value::Mapping(mapping))
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        let (tag, contents) = data.variant::<String>()?;
+        if tag.is_empty() {
+            return Err(A::Error::custom("empty YAML tag is not allowed"));
+        }
+        let value = contents.newtype_variant_seed(self)?;
+        Ok(Value::Tagged(Box::new(TaggedValue { tag: Tag::new(tag), value })))
+    }
+}
+
+/// Deserializes a mapping key with `keys`, failing when `strict`
+/// is set and `mapping` already has it. The check is made while
+/// the key is being deserialized so the error points at it
+/// rather than at the start of the mapping
+#[derive(Clone, Copy)]
+struct Key<'m, 'a> {
+    keys: Keys<'a>,
+    mapping: &'m Mapping,
+}
+
+impl<'m, 'a> Key<'m, 'a> {
+    fn check<E: Error>(self, key: Result<Value, E>) -> Result<Value, E> {
+        match key {
+            Ok(key) if self.keys.strict && self.mapping.contains_key(&key) => {
+                Err(E::custom(format!("duplicate key {}", key_name(&key))))
+            },
+            other => other,
+        }
+    }
+}
+
+impl<'m, 'a, 'de> DeserializeSeed<'de> for Key<'m, 'a> {
+    type Value = Value;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'m, 'a, 'de> Visitor<'de> for Key<'m, 'a> {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.keys.expecting(formatter)
+    }
+
+    fn visit_bool<E: Error>(self, b: bool) -> Result<Value, E> {
+        self.check(self.keys.visit_bool(b))
+    }
+
+    fn visit_i64<E: Error>(self, i: i64) -> Result<Value, E> {
+        self.check(self.keys.visit_i64(i))
+    }
+
+    fn visit_u64<E: Error>(self, u: u64) -> Result<Value, E> {
+        self.check(self.keys.visit_u64(u))
+    }
+
+    fn visit_f64<E: Error>(self, f: f64) -> Result<Value, E> {
+        self.check(self.keys.visit_f64(f))
+    }
+
+    fn visit_str<E: Error>(self, s: &str) -> Result<Value, E> {
+        self.check(self.keys.visit_str(s))
+    }
+
+    fn visit_borrowed_str<E: Error>(self, s: &'de str) -> Result<Value, E> {
+        self.check(self.keys.visit_borrowed_str(s))
+    }
+
+    fn visit_string<E: Error>(self, s: String) -> Result<Value, E> {
+        self.check(self.keys.visit_string(s))
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Value, E> {
+        self.check(self.keys.visit_unit())
+    }
+
+    fn visit_none<E: Error>(self) -> Result<Value, E> {
+        self.check(self.keys.visit_none())
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        self.check(self.keys.visit_some(deserializer))
+    }
+
+    fn visit_seq<A>(self, seq: A) -> Result<Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        self.check(self.keys.visit_seq(seq))
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        self.check(self.keys.visit_map(map))
+    }
+
+    fn visit_enum<A>(self, data: A) -> Result<Value, A::Error>
+    where
+        A: EnumAccess<'de>,
+    {
+        self.check(self.keys.visit_enum(data))
+    }
+}
+
+/// Was `s`, a slice of `source`, written there as an untagged
+/// plain scalar or one tagged `!!timestamp`. A quoted scalar or
+/// one with any other tag, like `!!str`, is a string no matter
+/// what it looks like. serde_yaml hands over plain and quoted
+/// scalars without escapes as slices of the source, anything
+/// else isn't found in `source` and is left alone
+fn is_timestamp_scalar(source: &str, s: &str) -> bool {
+    let start = (s.as_ptr() as usize).wrapping_sub(source.as_ptr() as usize);
+    let before = match source.get(..start) {
+        Some(before) => before,
+        None => return false,
+    };
+    let (before, quoted) = match before.strip_suffix(['"', '\'']) {
+        Some(before) => (before, true),
+        None => (before, false),
+    };
+    // the properties written before the scalar, anchors are
+    // skipped to find a tag
+    let tag = before.rsplit(|c: char| c.is_whitespace() || "[{,".contains(c))
+        .filter(|token| !token.is_empty())
+        .find(|token| !token.starts_with('&'))
+        .filter(|token| token.starts_with('!'));
+    match tag {
+        Some(tag) => tag == "!!timestamp",
+        None => !quoted,
+    }
+}
@@ -0,0 +1,2594 @@
+//! Convert YAML documents into JSON documents, and back again
+extern crate base64;
+extern crate flate2;
+#[cfg(feature = "dir")]
+extern crate fs2;
+extern crate glob;
+extern crate indicatif;
+extern crate json5;
+extern crate jsonschema;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "dir")]
+extern crate notify;
+extern crate rayon;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate toml;
+#[cfg(feature = "url")]
+extern crate ureq;
+#[cfg(feature = "dir")]
+extern crate walkdir;
+
+use std::{
+    collections::BTreeMap,
+    fs::{copy, create_dir_all, read, read_to_string, remove_file, rename, write, File},
+    io::{sink, stdin, stdout, BufRead, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex},
+    time::{Duration, Instant},
+};
+#[cfg(feature = "dir")]
+use std::{io::IsTerminal, sync::mpsc::channel};
+
+use flate2::read::GzDecoder;
+use indicatif::ProgressBar;
+#[cfg(feature = "dir")]
+use indicatif::{ProgressDrawTarget, ProgressStyle};
+use keys::Keys;
+use rayon::prelude::*;
+use serde::de::DeserializeSeed;
+use serde_yaml::Value;
+
+mod keys;
+pub mod transform;
+
+use transform::key_name;
+
+/// Which way a conversion is going, this decides the input
+/// format unless `Options::in_format` is set
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Direction {
+    #[default]
+    YamlToJson,
+    JsonToYaml,
+}
+
+/// The format of the files being converted
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum InputFormat {
+    Yaml,
+    Json,
+}
+
+impl InputFormat {
+    /// The file name suffixes picked up in directory mode
+    fn exts(self) -> &'static [&'static str] {
+        match self {
+            InputFormat::Yaml => &["yaml", "yml"],
+            InputFormat::Json => &["json"],
+        }
+    }
+}
+
+/// The format yaml documents are converted into
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Toml,
+    /// Always indented, with unquoted keys where possible and
+    /// trailing commas, see `to_json5`
+    Json5,
+}
+
+/// How yaml output is laid out
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum YamlStyle {
+    /// Nested collections on their own indented lines
+    #[default]
+    Block,
+    /// Every document on one line with `{}` and `[]`, see
+    /// `to_yaml_flow`
+    Flow,
+}
+
+/// What happens to values with a yaml tag like `!Foo`, which
+/// json has no way to write
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum TagHandling {
+    /// The tag is dropped and the value kept
+    #[default]
+    Strip,
+    /// The value is written as `{"__tag__": "!Foo", "value": ...}`
+    Wrap,
+    /// A tagged value fails the conversion
+    Error,
+}
+
+/// The case mapping keys are rewritten into
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum KeyCase {
+    /// Keys are left as written
+    #[default]
+    None,
+    Upper,
+    Lower,
+}
+
+/// How numeric scalars are written
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NumberMode {
+    /// Integers and floats keep the type they were parsed as
+    #[default]
+    Preserve,
+    /// Every number is written as a float
+    AllFloats,
+    /// Every number is written as a string
+    AllStrings,
+}
+
+/// How null values are written
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NullAs {
+    /// Nulls are written as null
+    #[default]
+    Null,
+    /// Nulls are written as `""`
+    EmptyString,
+    /// Mapping entries with a null value are dropped, nulls
+    /// anywhere else are kept
+    Omit,
+}
+
+/// How decoded `!!binary` values are written
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum BinaryAs {
+    /// A sequence of the decoded bytes, `[104, 105]`
+    #[default]
+    Bytes,
+    /// The decoded bytes encoded again as standard base64,
+    /// without the line breaks yaml allows
+    Base64,
+}
+
+/// What happens to yaml comments, which have no place in
+/// the converted output
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub enum CommentHandling {
+    /// Comments are discarded
+    #[default]
+    Drop,
+    /// Comments are written to a `.comments.txt` file next
+    /// to the output file, prefixed with their line number
+    Sidecar,
+}
+
+impl OutputFormat {
+    fn ext(self) -> &'static str {
+        match self {
+            OutputFormat::Json => "json",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Json5 => "json5",
+        }
+    }
+}
+
+/// Settings shared by every conversion
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Which way to convert
+    pub direction: Direction,
+    /// Pretty print the json output
+    pub pretty: bool,
+    /// Round float values to this many decimal places, this is
+    /// lossy. Integers and strings are never changed
+    pub float_precision: Option<u32>,
+    /// Write the smallest json possible. Output is always
+    /// compact with no whitespace outside of strings and no
+    /// trailing newline, numbers are written in their shortest
+    /// form without changing their type so `1.0` stays a float
+    pub minify: bool,
+    /// End json output with a newline, by default it ends with
+    /// the closing bracket
+    pub trailing_newline: bool,
+    /// The indent used for pretty printed json in place of two
+    /// spaces, setting it implies `pretty`
+    pub indent: Option<String>,
+    /// How many directories deep `convert_dir` looks for files
+    pub depth: usize,
+    /// Skip the informational messages printed to stdout
+    pub quiet: bool,
+    /// The format yaml is converted into, ignored when
+    /// converting json into yaml
+    pub output_format: OutputFormat,
+    /// Overwrite output files that already exist
+    pub force: bool,
+    /// The number of threads `convert_dir` uses, defaults to
+    /// one per core
+    pub jobs: Option<usize>,
+    /// Parse every input and report where it would be written
+    /// without writing anything
+    pub dry_run: bool,
+    /// Only print how many files directory or glob mode would
+    /// convert, without reading or writing any of them
+    pub count_only: bool,
+    /// Write each output in directory mode next to its input
+    /// instead of under the output directory
+    pub beside: bool,
+    /// Name yaml outputs `.yml` instead of `.yaml`
+    pub yml: bool,
+    /// Overrides the extension given to each output file when
+    /// converting more than one file
+    pub out_ext: Option<String>,
+    /// What to do with the comments in a yaml file
+    pub comments: CommentHandling,
+    /// When combining a directory into one file, write a
+    /// mapping keyed by each file's relative path instead of a
+    /// sequence, see `combine_dir`
+    pub combine_keyed: bool,
+    /// What to do with tagged values when converting yaml,
+    /// yaml output keeps its tags
+    pub tags: TagHandling,
+    /// Print the number of mappings, sequences and scalars in
+    /// each converted file and how deeply they nest
+    pub stats_tree: bool,
+    /// Print the size of each input and its output in bytes
+    pub size_report: bool,
+    /// Rewrite every string mapping key into this case
+    pub case_keys: KeyCase,
+    /// Fail with `Error::NoMatches` when directory or glob mode
+    /// finds no files to convert, by default this is a warning
+    pub error_on_empty: bool,
+    /// Fail unless each document is a sequence of mappings that
+    /// all have the same keys, a table of records
+    pub tabular: bool,
+    /// Write a json summary of each batch of files to this path,
+    /// see `Report`
+    pub report: Option<PathBuf>,
+    /// Lock the output directory in directory mode so runs into
+    /// the same directory don't write over each other's files
+    pub lock: bool,
+    /// Print how many files a batch converted, how long it took
+    /// and how the files were spread over the worker threads
+    pub stats: bool,
+    /// Wrap each json document as `{"_source": path, "data": doc}`
+    /// naming the file it was converted from
+    pub annotate_source: bool,
+    /// Leave an existing output alone when it already holds
+    /// exactly what would be written, it doesn't need `force`
+    pub skip_unchanged: bool,
+    /// How many times reading an input or writing an output is
+    /// tried again after a transient io error
+    pub retries: usize,
+    /// Move an existing output aside to `<output>.bak` before
+    /// writing over it
+    pub backup: bool,
+    /// Only convert the `---` fenced front matter at the start
+    /// of each input, see `front_matter`
+    pub front_matter: bool,
+    /// The layout of yaml output when converting json into yaml
+    pub yaml_style: YamlStyle,
+    /// Overrides the input format implied by `direction`, json
+    /// input with `YamlToJson` reformats json
+    pub in_format: Option<InputFormat>,
+    /// Keep converting the rest of the files after one fails
+    /// instead of stopping at the first failure
+    pub keep_going: bool,
+    /// Flatten nested mappings into a single mapping with
+    /// `flatten_sep` joined keys
+    pub flatten: bool,
+    /// The separator between flattened keys
+    pub flatten_sep: String,
+    /// Also flatten sequences, using each index as a key
+    pub flatten_arrays: bool,
+    /// The file name suffixes picked up when converting a
+    /// directory, defaults to the input format's extensions
+    pub match_ext: Option<Vec<String>>,
+    /// When merging files, concatenate sequences instead of
+    /// replacing them
+    pub merge_arrays: bool,
+    /// Sort the keys of every mapping instead of keeping the
+    /// order they were written in
+    pub sort_keys: bool,
+    /// A dotted path to the only part of each document that
+    /// is converted
+    pub select: Option<String>,
+    /// Replace `${VAR}` in string values with the value of the
+    /// environment variable
+    pub expand_env: bool,
+    /// Used in place of unset environment variables instead
+    /// of failing
+    pub env_default: Option<String>,
+    /// Decode `!!binary` values instead of keeping their
+    /// base64 text
+    pub decode_binary: bool,
+    /// How decoded binary values are written
+    pub binary_as: BinaryAs,
+    /// Trim the whitespace around string values
+    pub trim_strings: bool,
+    /// Write yaml timestamps as ISO-8601 UTC strings, see
+    /// `transform::timestamp`. Only plain scalars and those
+    /// tagged `!!timestamp` are timestamps, quoted ones and json
+    /// strings are left alone
+    pub timestamps: bool,
+    /// How nulls are written
+    pub nulls: NullAs,
+    /// How numbers are written
+    pub numbers: NumberMode,
+    /// Compare what would be written to each existing output
+    /// instead of writing it, failing for any that differ
+    pub check: bool,
+    /// Write each output to a temporary file that is renamed
+    /// into place once it is complete
+    pub atomic: bool,
+    /// Read the output back before writing it, failing when
+    /// it doesn't match the input
+    pub verify: bool,
+    /// Files in directory mode larger than this many bytes
+    /// are skipped
+    pub max_size: Option<u64>,
+    /// How many leading directories of each file's path
+    /// relative to the input directory are dropped from its
+    /// output path
+    pub strip_components: usize,
+    /// Files in directory mode matching any of these, by name
+    /// or by path relative to the directory, are skipped
+    pub exclude: Vec<glob::Pattern>,
+    /// Follow symlinks while walking a directory instead of
+    /// skipping them
+    pub follow_symlinks: bool,
+    /// Print how long each phase of every conversion took
+    pub time: bool,
+    /// Report each alias that was expanded while parsing
+    pub expand_anchors: bool,
+    /// Fail when a line of yaml is indented with a tab, even if
+    /// it still parses
+    pub lint_tabs: bool,
+    /// Fail on mappings that repeat a key instead of keeping
+    /// the last value
+    pub strict_keys: bool,
+    /// Write each yaml document as compact json on its own
+    /// line instead of collecting them into an array. The output
+    /// is always json, `direction` and `output_format` are ignored
+    pub ndjson: bool,
+    /// Each converted document replaces the placeholder in this
+    /// template, see `load_template`
+    pub template: Option<Value>,
+    /// Every converted document must match this json schema,
+    /// see `load_schema`
+    pub schema: Option<Arc<jsonschema::Validator>>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            direction: Direction::default(),
+            pretty: false,
+            float_precision: None,
+            minify: false,
+            trailing_newline: false,
+            indent: None,
+            depth: 1,
+            quiet: false,
+            output_format: OutputFormat::default(),
+            force: false,
+            jobs: None,
+            dry_run: false,
+            count_only: false,
+            beside: false,
+            yml: false,
+            out_ext: None,
+            comments: CommentHandling::default(),
+            combine_keyed: false,
+            skip_unchanged: false,
+            retries: 3,
+            backup: false,
+            annotate_source: false,
+            report: None,
+            lock: true,
+            stats: false,
+            tabular: false,
+            error_on_empty: false,
+            case_keys: KeyCase::default(),
+            stats_tree: false,
+            size_report: false,
+            tags: TagHandling::default(),
+            front_matter: false,
+            yaml_style: YamlStyle::default(),
+            in_format: None,
+            keep_going: false,
+            flatten: false,
+            flatten_sep: ".".to_string(),
+            flatten_arrays: false,
+            merge_arrays: false,
+            sort_keys: false,
+            select: None,
+            expand_env: false,
+            env_default: None,
+            decode_binary: false,
+            binary_as: BinaryAs::Bytes,
+            trim_strings: false,
+            timestamps: false,
+            nulls: NullAs::Null,
+            numbers: NumberMode::Preserve,
+            check: false,
+            atomic: false,
+            verify: false,
+            max_size: None,
+            strip_components: 0,
+            exclude: Vec::new(),
+            follow_symlinks: false,
+            time: false,
+            expand_anchors: false,
+            lint_tabs: false,
+            strict_keys: false,
+            ndjson: false,
+            match_ext: None,
+            template: None,
+            schema: None,
+        }
+    }
+}
+
+impl Options {
+    fn input_format(&self) -> InputFormat {
+        self.in_format.unwrap_or(match self.direction {
+            Direction::YamlToJson => InputFormat::Yaml,
+            Direction::JsonToYaml => InputFormat::Json,
+        })
+    }
+
+    fn input_exts(&self) -> Vec<&str> {
+        match &self.match_ext {
+            Some(exts) => exts.iter().map(|ext| ext.trim_start_matches('.')).collect(),
+            None => self.input_format().exts().to_vec(),
+        }
+    }
+
+    /// The output file name for `file_name`, the longest
+    /// recognized suffix (after any `.gz`) is swapped for the
+    /// output extension
+    fn output_name(&self, file_name: &str) -> String {
+        let file_name = file_name.strip_suffix(".gz").unwrap_or(file_name);
+        let stem = self.input_exts().into_iter()
+            .map(|ext| format!(".{}", ext))
+            .filter(|ext| file_name.len() > ext.len() && file_name.ends_with(ext.as_str()))
+            .max_by_key(|ext| ext.len())
+            .map(|ext| file_name[..file_name.len() - ext.len()].to_string())
+            .unwrap_or_else(|| Path::new(file_name)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_else(|| file_name.to_string()));
+        format!("{}.{}", stem, self.output_ext())
+    }
+
+    /// The extension given to each output file in directory mode,
+    /// `out_ext` wins over the one implied by the output format
+    fn output_ext(&self) -> &str {
+        if let Some(ext) = &self.out_ext {
+            return ext.trim_start_matches('.');
+        }
+        match self.direction {
+            Direction::YamlToJson => self.output_format.ext(),
+            Direction::JsonToYaml if self.yml => "yml",
+            Direction::JsonToYaml => "yaml",
+        }
+    }
+}
+
+/// Convert every matching file in `from_path`, writing the
+/// results into `to_path`. Symlinks are skipped unless
+/// `opts.follow_symlinks` is set, a link that loops back to
+/// one of its parents is skipped with a warning. A `to_path`
+/// of `-` writes every file to stdout the way `combine_dir`
+/// does, in sorted order
+#[cfg(feature = "dir")]
+pub fn convert_dir(from_path: &Path, to_path: &Path, opts: &Options) -> Result<(), Error> {
+    if opts.count_only {
+        println!("{}", dir_inputs(from_path, opts)?.0.len());
+        return Ok(());
+    }
+    if is_std_stream(to_path) {
+        return combine_dir(from_path, to_path, opts);
+    }
+    if !opts.quiet {
+        info!("Converting the files from {} to {}", from_path.display(), to_path.display());
+    }
+    let (inputs, skipped) = dir_inputs(from_path, opts)?;
+    if inputs.is_empty() {
+        no_matches(&format!("in {}", from_path.display()), opts)?;
+    }
+    // held until the conversion is done
+    let _lock = lock_dir(to_path, opts)?;
+    let files = inputs.into_iter()
+        .map(|file| dir_target(from_path, to_path, &file, opts).map(|target| (file, target)))
+        .collect::<Result<Vec<(PathBuf, PathBuf)>, Error>>()?;
+    if opts.quiet || opts.dry_run || !stdout().is_terminal() {
+        return convert_all(&files, skipped, opts, None);
+    }
+    let progress = ProgressBar::with_draw_target(Some(files.len() as u64), ProgressDrawTarget::stdout())
+        .with_style(ProgressStyle::with_template("{bar:40} {pos}/{len} {wide_msg}")
+            .map_err(|e| Error::Io(format!("Failed to draw the progress bar: {}", e)))?);
+    let res = convert_all(&files, skipped, opts, Some(&progress));
+    progress.finish_and_clear();
+    res
+}
+
+/// Take the advisory lock on `.y2j.lock` in `to_path` so two
+/// runs into the same directory take turns, waiting for the
+/// other run when it is held. `to_path` is created when it
+/// doesn't exist yet, so the first runs into it take turns as
+/// well. Nothing is locked with `opts.lock` unset, when only
+/// checking, with `opts.beside` where `to_path` is the input
+/// directory or when `to_path` isn't a directory
+#[cfg(feature = "dir")]
+fn lock_dir(to_path: &Path, opts: &Options) -> Result<Option<DirLock>, Error> {
+    if !opts.lock || opts.dry_run || opts.check || opts.beside || is_std_stream(to_path) {
+        return Ok(None);
+    }
+    if !to_path.exists() {
+        create_dir_all(to_path).map_err(|e| write_error(to_path, &e))?;
+    } else if !to_path.is_dir() {
+        return Ok(None);
+    }
+    let path = to_path.join(".y2j.lock");
+    loop {
+        let file = File::create(&path).map_err(|e| write_error(&path, &e))?;
+        if fs2::FileExt::try_lock_exclusive(&file).is_err() {
+            if !opts.quiet {
+                info!("Waiting for another y2j run to finish with {}", to_path.display());
+            }
+            fs2::FileExt::lock_exclusive(&file).map_err(|e| Error::Io(format!("Failed to lock {}: {}", path.display(), e)))?;
+        }
+        // the run that held the lock removed the file before
+        // letting go, start over with a new one
+        if is_same_file(&file, &path) {
+            return Ok(Some(DirLock { file, path }));
+        }
+    }
+}
+
+/// The lock taken by `lock_dir`, the lock file is removed
+/// when it is dropped
+#[cfg(feature = "dir")]
+struct DirLock {
+    file: File,
+    path: PathBuf,
+}
+
+#[cfg(feature = "dir")]
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        // removed while still held, so a run waiting on it knows
+        // to start over
+        let _ = remove_file(&self.path);
+        let _ = fs2::FileExt::unlock(&self.file);
+    }
+}
+
+/// Is the open `file` still the one at `path`
+#[cfg(all(feature = "dir", unix))]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), ::std::fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.dev() == current.dev() && open.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+/// Is the open `file` still the one at `path`, an open file
+/// can't be removed on windows
+#[cfg(all(feature = "dir", not(unix)))]
+fn is_same_file(_file: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+/// Walk `from_path` for the files directory mode converts,
+/// sorted by path, along with how many files were skipped
+#[cfg(feature = "dir")]
+fn dir_inputs(from_path: &Path, opts: &Options) -> Result<(Vec<PathBuf>, usize), Error> {
+    let walker = walkdir::WalkDir::new(from_path)
+        .max_depth(opts.depth)
+        .min_depth(1)
+        .follow_links(opts.follow_symlinks);
+    let mut files = Vec::new();
+    let mut skipped = 0;
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => {
+                debug!("entry: {:?}", entry.path());
+                entry
+            },
+            Err(e) => {
+                if let (Some(path), Some(_)) = (e.path(), e.loop_ancestor()) {
+                    if !opts.quiet {
+                        warn!("skipping {}, it links back to one of its parent directories", path.display());
+                    }
+                }
+                continue;
+            },
+        };
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        if !is_input_file(entry.path(), opts) {
+            skipped += 1;
+            continue;
+        }
+        if is_excluded(from_path, entry.path(), opts) {
+            if opts.dry_run {
+                println!("would skip {}, it is excluded", entry.path().display());
+            }
+            skipped += 1;
+            continue;
+        }
+        if let (Some(max), Ok(meta)) = (opts.max_size, entry.metadata()) {
+            if meta.len() > max {
+                if !opts.quiet {
+                    warn!("skipping {}, it is {} bytes which is over --max-size", entry.path().display(), meta.len());
+                }
+                skipped += 1;
+                continue;
+            }
+        }
+        files.push(entry.path().to_path_buf());
+    }
+    // the walk order depends on the file system
+    files.sort();
+    Ok((files, skipped))
+}
+
+/// Convert every matching file in `from_path` into a single
+/// `to_path`, a sequence with one entry per file or with
+/// `opts.combine_keyed` a mapping from each file's path
+/// relative to `from_path` to its contents. With `opts.ndjson`
+/// each file is written on its own line instead
+#[cfg(feature = "dir")]
+pub fn combine_dir(from_path: &Path, to_path: &Path, opts: &Options) -> Result<(), Error> {
+    if opts.dry_run {
+        println!("would combine the files from {} into {}", from_path.display(), to_path.display());
+    } else if !is_std_stream(to_path) && !opts.quiet {
+        info!("Combining the files from {} into {}", from_path.display(), to_path.display());
+    }
+    check_output_path(to_path, opts)?;
+    let (inputs, skipped) = dir_inputs(from_path, opts)?;
+    if inputs.is_empty() {
+        no_matches(&format!("in {}", from_path.display()), opts)?;
+    }
+    let mut values = Vec::new();
+    let mut keyed = serde_yaml::Mapping::new();
+    for file in &inputs {
+        let content = read_input(file, opts)?;
+        let value = prepare(collect_documents(parse(&content, file, opts)?), opts).map_err(|e| e.with_path(file))?;
+        let value = annotate(value, file, opts);
+        if opts.combine_keyed {
+            let relative = file.strip_prefix(from_path).unwrap_or(file);
+            keyed.insert(Value::String(relative.display().to_string()), value);
+        } else {
+            values.push(value);
+        }
+    }
+    let docs = match (opts.ndjson, opts.combine_keyed) {
+        (true, false) => values,
+        (true, true) => keyed.into_iter().map(|(_, value)| value).collect(),
+        (false, false) => vec![Value::Sequence(values)],
+        (false, true) => vec![Value::Mapping(keyed)],
+    };
+    check_output(&docs, opts).map_err(|e| e.with_path(to_path))?;
+    write_output(&docs, to_path, to_path, opts)?;
+    if !opts.quiet && !opts.dry_run && !is_std_stream(to_path) {
+        info!("Combined {} files ({} skipped)", inputs.len(), skipped);
+    }
+    Ok(())
+}
+
+/// Does the file name end with one of the recognized
+/// extensions, optionally followed by `.gz`
+#[cfg(feature = "dir")]
+fn is_input_file(path: &Path, opts: &Options) -> bool {
+    path.file_name()
+        .map(|name| {
+            let name = name.to_string_lossy();
+            let name = name.strip_suffix(".gz").unwrap_or(&name);
+            opts.input_exts().iter().any(|ext| name.ends_with(&format!(".{}", ext)))
+        })
+        .unwrap_or(false)
+}
+
+/// Does the name of `file` or its path relative to `from_path`
+/// match one of the `opts.exclude` patterns
+#[cfg(feature = "dir")]
+fn is_excluded(from_path: &Path, file: &Path, opts: &Options) -> bool {
+    let relative = file.strip_prefix(from_path).unwrap_or(file);
+    opts.exclude.iter().any(|pattern| {
+        pattern.matches_path(relative)
+            || file.file_name().map(|name| pattern.matches(&name.to_string_lossy())).unwrap_or(false)
+    })
+}
+
+/// Build the output path for `file` found under `from_path`
+/// by mirroring its location under `to_path`, less the first
+/// `opts.strip_components` directories, or with `opts.beside`
+/// next to `file`. Missing nested directories are created as
+/// each file is converted
+#[cfg(feature = "dir")]
+fn dir_target(from_path: &Path, to_path: &Path, file: &Path, opts: &Options) -> Result<PathBuf, Error> {
+    if opts.beside {
+        let file_name = file.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        return Ok(file.with_file_name(opts.output_name(&file_name)));
+    }
+    let relative = file.strip_prefix(from_path).map_err(|_| Error::Io(format!("Failed to find {} relative to {}", file.display(), from_path.display())))?;
+    let file_name = relative.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let sub_dir = relative.parent()
+        .map(|dir| dir.components().skip(opts.strip_components).collect::<PathBuf>())
+        .unwrap_or_default();
+    Ok(to_path.join(sub_dir).join(opts.output_name(&file_name)))
+}
+
+/// Convert every matching file in `from_path` and then keep
+/// converting each one as it changes, this never returns
+/// unless the watch fails. Outputs are always overwritten
+#[cfg(feature = "dir")]
+pub fn watch_dir(from_path: &Path, to_path: &Path, opts: &Options) -> Result<(), Error> {
+    let opts = Options {
+        force: true,
+        ..opts.clone()
+    };
+    // held for as long as the watch runs
+    let _lock = lock_dir(to_path, &opts)?;
+    convert_dir(from_path, to_path, &Options { lock: false, ..opts.clone() })?;
+    // events are reported with the canonical path on some platforms
+    let from_path = from_path.canonicalize()?;
+    let (tx, rx) = channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    notify::Watcher::watch(&mut watcher, &from_path, notify::RecursiveMode::Recursive)?;
+    if !opts.quiet {
+        info!("Watching {} for changes", from_path.display());
+    }
+    loop {
+        let mut changed = Vec::new();
+        let event = rx.recv().map_err(|e| Error::Io(format!("Stopped watching {}: {}", from_path.display(), e)))?;
+        changed_paths(event, &mut changed);
+        // an editor save is often several events, wait for them
+        // to settle before converting
+        while let Ok(event) = rx.recv_timeout(Duration::from_millis(200)) {
+            changed_paths(event, &mut changed);
+        }
+        changed.sort();
+        changed.dedup();
+        for path in changed {
+            let in_depth = path.strip_prefix(&from_path)
+                .map(|rel| rel.components().count() <= opts.depth)
+                .unwrap_or(false);
+            if !path.is_file() || !in_depth || !is_input_file(&path, &opts) {
+                continue;
+            }
+            let res = dir_target(&from_path, to_path, &path, &opts)
+                .and_then(|target| convert_pair(&path, &target, &opts));
+            if let Err(e) = res {
+                error!("Failed to convert {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Collect the paths of a create or modify event
+#[cfg(feature = "dir")]
+fn changed_paths(event: notify::Result<notify::Event>, paths: &mut Vec<PathBuf>) {
+    if let Ok(event) = event {
+        if event.kind.is_create() || event.kind.is_modify() {
+            paths.extend(event.paths);
+        }
+    }
+}
+
+/// Convert every file matching the glob `pattern`, writing
+/// the results into `to_path`
+pub fn convert_glob(pattern: &str, to_path: &Path, opts: &Options) -> Result<(), Error> {
+    if !opts.quiet && !opts.count_only {
+        info!("Converting the files matching {} to {}", pattern, to_path.display());
+    }
+    let paths = glob::glob(pattern).map_err(|e| Error::Io(format!("Invalid glob pattern {}: {}", pattern, e)))?;
+    let mut files: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut skipped = 0;
+    for path in paths.flatten() {
+        match path.file_name() {
+            Some(file_name) if path.is_file() => {
+                let target = to_path.join(opts.output_name(&file_name.to_string_lossy()));
+                files.push((path, target));
+            },
+            _ => skipped += 1,
+        }
+    }
+    if opts.count_only {
+        println!("{}", files.len());
+        return Ok(());
+    }
+    if files.is_empty() {
+        no_matches(&format!("matching {}", pattern), opts)?;
+    }
+    convert_all(&files, skipped, opts, None)
+}
+
+/// Report that no files were found, `place` says where they
+/// were looked for. This is a warning unless
+/// `opts.error_on_empty` is set
+fn no_matches(place: &str, opts: &Options) -> Result<(), Error> {
+    let msg = format!("no files to convert were found {}, check the path and --match-ext", place);
+    if opts.error_on_empty {
+        return Err(Error::NoMatches(msg));
+    }
+    if !opts.quiet {
+        warn!("{}", msg);
+    }
+    Ok(())
+}
+
+/// Convert each of `from_paths` into the directory `to_path`,
+/// naming each output file the way `convert_dir` does
+pub fn convert_files(from_paths: &[PathBuf], to_path: &Path, opts: &Options) -> Result<(), Error> {
+    if !to_path.is_dir() {
+        return Err(Error::Io(format!("outpath must be a directory when converting more than one file\n{}", to_path.display())));
+    }
+    let mut files: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for path in from_paths {
+        let file_name = path.file_name().ok_or(Error::Io(format!("{} is not a file", path.display())))?;
+        files.push((path.clone(), to_path.join(opts.output_name(&file_name.to_string_lossy()))));
+    }
+    convert_all(&files, 0, opts, None)
+}
+
+/// Convert each file listed in `list`, one path per line with
+/// blank lines and `#` comments ignored. Outputs are written
+/// into `to_path` when given, otherwise next to each input
+pub fn convert_list(list: &Path, to_path: Option<&Path>, opts: &Options) -> Result<(), Error> {
+    let content = read_to_string(list).map_err(|e| Error::Io(format!("Failed to read {}: {}", list.display(), e)))?;
+    let from_paths = content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    if let Some(to_path) = to_path {
+        return convert_files(&from_paths, to_path, opts);
+    }
+    let mut files: Vec<(PathBuf, PathBuf)> = Vec::new();
+    for path in from_paths {
+        let file_name = path.file_name().ok_or(Error::Io(format!("{} is not a file", path.display())))?;
+        let target = path.with_file_name(opts.output_name(&file_name.to_string_lossy()));
+        files.push((path, target));
+    }
+    convert_all(&files, 0, opts, None)
+}
+
+/// Convert each `(from, to)` pair in parallel, stopping at the
+/// first failure unless `opts.keep_going` is set, advancing
+/// `progress` after each file, see `convert_pair`. Finishes with a count of the
+/// files converted, the `skipped` files that weren't inputs
+/// and the files that failed
+fn convert_all(files: &[(PathBuf, PathBuf)], skipped: usize, opts: &Options, progress: Option<&ProgressBar>) -> Result<(), Error> {
+    // the progress bar replaces the line printed for each file
+    let file_opts = Options { quiet: opts.quiet || progress.is_some(), ..opts.clone() };
+    let converted = AtomicUsize::new(0);
+    let unchanged = AtomicUsize::new(0);
+    let total = Mutex::new(Timings::default());
+    // how many files each worker thread converted
+    let per_thread = Mutex::new(BTreeMap::new());
+    let results = Mutex::new(Vec::new());
+    let start = Instant::now();
+    let convert_one = |from: &PathBuf, to: &PathBuf| {
+        let res = convert_pair(from, to, &file_opts).map(|timings| {
+            converted.fetch_add(1, Ordering::Relaxed);
+            if timings.unchanged {
+                unchanged.fetch_add(1, Ordering::Relaxed);
+            }
+            if let Ok(mut total) = total.lock() {
+                total.add(timings);
+            }
+        });
+        if let Ok(mut per_thread) = per_thread.lock() {
+            *per_thread.entry(rayon::current_thread_index().unwrap_or(0)).or_insert(0) += 1;
+        }
+        if let (true, Ok(mut results)) = (opts.report.is_some(), results.lock()) {
+            results.push(FileReport {
+                input: from.display().to_string(),
+                output: to.display().to_string(),
+                status: if res.is_ok() { "ok" } else { "failed" },
+                error: res.as_ref().err().map(|e| e.to_string()),
+            });
+        }
+        if let Some(progress) = progress {
+            progress.set_message(from.display().to_string());
+            progress.inc(1);
+        }
+        res
+    };
+    let run = || if opts.keep_going {
+        files.par_iter()
+            .map(|(from, to)| {
+                let res = convert_one(from, to);
+                // out of date outputs are listed together at the end
+                if let Some(e) = res.as_ref().err().filter(|e| !matches!(e, Error::OutOfDate(_))) {
+                    let report = || error!("Failed to convert {}: {}", from.display(), e);
+                    match progress {
+                        Some(progress) => progress.suspend(report),
+                        None => report(),
+                    }
+                }
+                res
+            })
+            .filter_map(Result::err)
+            .collect::<Vec<Error>>()
+    } else {
+        files.par_iter()
+            .try_for_each(|(from, to)| convert_one(from, to))
+            .err()
+            .into_iter()
+            .collect()
+    };
+    let mut errors = match opts.jobs {
+        Some(n) => rayon::ThreadPoolBuilder::new()
+            .num_threads(n)
+            .build()
+            .map_err(|e| Error::Io(format!("Failed to start {} conversion threads: {}", n, e)))?
+            .install(run),
+        None => run(),
+    };
+    let elapsed = start.elapsed();
+    let converted = converted.into_inner();
+    if !opts.quiet && !opts.dry_run && opts.skip_unchanged {
+        info!("{} {} files ({} skipped, {} unchanged, {} failed)", if opts.check { "Checked" } else { "Converted" },
+            converted, skipped, unchanged.into_inner(), errors.len());
+    } else if !opts.quiet && !opts.dry_run {
+        info!("{} {} files ({} skipped, {} failed)", if opts.check { "Checked" } else { "Converted" },
+            converted, skipped, errors.len());
+    }
+    let total = total.into_inner().unwrap_or_default();
+    if opts.time {
+        eprintln!("total: {}", total);
+    }
+    if opts.stats {
+        print_stats(files.len(), elapsed, &total, &per_thread.into_inner().unwrap_or_default());
+    }
+    if let Some(path) = &opts.report {
+        let mut files = results.into_inner().unwrap_or_default();
+        files.sort_by(|a, b| a.input.cmp(&b.input));
+        let report = Report { converted, skipped, failed: errors.len(), files };
+        if let Err(e) = write_report(&report, path) {
+            if errors.is_empty() {
+                return Err(e);
+            }
+            error!("{}", e);
+        }
+    }
+    // a failed check only lists what is out of date
+    let out_of_date = errors.iter()
+        .filter_map(|e| match e {
+            Error::OutOfDate(msgs) => Some(msgs.iter().cloned()),
+            _ => None,
+        })
+        .flatten()
+        .collect::<Vec<_>>();
+    if !out_of_date.is_empty() {
+        errors.retain(|e| !matches!(e, Error::OutOfDate(_)));
+        errors.push(Error::OutOfDate(out_of_date));
+    }
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.remove(0)),
+        _ => Err(Error::Batch(errors)),
+    }
+}
+
+/// The summary of a batch written with `opts.report`
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub converted: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Every file that was converted or failed, files after the
+    /// first failure aren't listed unless `keep_going` is set
+    pub files: Vec<FileReport>,
+}
+
+/// How converting one file of a batch went
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub input: String,
+    pub output: String,
+    /// Either `ok` or `failed`
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn write_report(report: &Report, path: &Path) -> Result<(), Error> {
+    let out = File::create(path).map_err(|e| write_error(path, &e))?;
+    serde_json::to_writer_pretty(BufWriter::new(out), report)
+        .map_err(|e| Error::Io(format!("Failed to write the report {}: {}", path.display(), e)))
+}
+
+/// Create the missing directories above `to_path`, nested
+/// outputs in directory mode may need them
+fn create_parent(to_path: &Path, opts: &Options) -> Result<(), Error> {
+    match to_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.exists() && !opts.dry_run && !opts.check => {
+            create_dir_all(dir).map_err(|e| write_error(dir, &e))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Report how a batch was spread over the worker threads. The
+/// phase times are summed over every thread, so comparing
+/// reading and writing to parsing and preparing hints at
+/// whether the batch is bound by io or cpu
+fn print_stats(files: usize, elapsed: Duration, total: &Timings, per_thread: &BTreeMap<usize, usize>) {
+    let secs = elapsed.as_secs_f64();
+    let rate = if secs > 0.0 { files as f64 / secs } else { 0.0 };
+    eprintln!("stats: {} files in {:?} on {} threads, {:.1} files/sec", files, elapsed, per_thread.len(), rate);
+    eprintln!("  read and write {:?}, parse and prepare {:?}", total.read + total.write, total.parse + total.prepare);
+    for (thread, count) in per_thread {
+        eprintln!("  thread {}: {} files", thread, count);
+    }
+}
+
+/// Convert a single file, either path may be `-` to use
+/// stdin or stdout. serde_yaml buffers its entire input no
+/// matter how it is handed over, so the input is read into
+/// memory while the output is streamed. When `to_path` is an
+/// existing directory the output is written into it, named the
+/// way `convert_dir` names it. Converting a file onto itself
+/// is an error, `convert_in_place` is the way to do that
+pub fn convert(from_path: &Path, to_path: &Path, opts: &Options) -> Result<(), Error> {
+    if to_path.is_dir() {
+        let file_name = from_path.file_name()
+            .filter(|_| !is_std_stream(from_path))
+            .ok_or(Error::Io(format!("outpath is a directory, an output name can't be chosen for {}\n{}", from_path.display(), to_path.display())))?;
+        let to_path = to_path.join(opts.output_name(&file_name.to_string_lossy()));
+        check_not_input(from_path, &to_path)?;
+        return convert_timed(from_path, &to_path, opts).map(|_| ());
+    }
+    check_not_input(from_path, to_path)?;
+    convert_timed(from_path, to_path, opts).map(|_| ())
+}
+
+/// Fail when `to_path` is the same file as `from_path`, which
+/// would replace the input with its own conversion. Paths that
+/// don't exist yet can't be the input
+fn check_not_input(from_path: &Path, to_path: &Path) -> Result<(), Error> {
+    if is_std_stream(from_path) || is_std_stream(to_path) {
+        return Ok(());
+    }
+    match (from_path.canonicalize(), to_path.canonicalize()) {
+        (Ok(from), Ok(to)) if from == to => Err(Error::Io(format!(
+            "{} is both the input and the output, use --in-place to convert a file onto itself", from_path.display()))),
+        _ => Ok(()),
+    }
+}
+
+/// How long each phase of converting a file took. The output
+/// is streamed so serializing and writing are timed together
+#[derive(Debug, Clone, Copy, Default)]
+struct Timings {
+    read: Duration,
+    parse: Duration,
+    prepare: Duration,
+    write: Duration,
+    /// Nothing was written since the output was already up to
+    /// date, see `Options::skip_unchanged`
+    unchanged: bool,
+}
+
+impl Timings {
+    fn add(&mut self, other: Timings) {
+        self.read += other.read;
+        self.parse += other.parse;
+        self.prepare += other.prepare;
+        self.write += other.write;
+    }
+}
+
+impl ::std::fmt::Display for Timings {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "read {:?}, parse {:?}, prepare {:?}, serialize and write {:?}, total {:?}",
+            self.read, self.parse, self.prepare, self.write,
+            self.read + self.parse + self.prepare + self.write)
+    }
+}
+
+/// Convert one file of a batch the way `convert` does, creating
+/// the directory the output goes in when it is missing
+fn convert_pair(from_path: &Path, to_path: &Path, opts: &Options) -> Result<Timings, Error> {
+    check_not_input(from_path, to_path)?;
+    create_parent(to_path, opts)?;
+    convert_timed(from_path, to_path, opts)
+}
+
+/// `convert`, printing how long each phase took with
+/// `opts.time`
+fn convert_timed(from_path: &Path, to_path: &Path, opts: &Options) -> Result<Timings, Error> {
+    let mut timings = Timings::default();
+    let to_stdout = is_std_stream(to_path);
+    if opts.dry_run {
+        println!("would convert {} to {}", &from_path.display(), &to_path.display());
+    } else if !to_stdout && !opts.quiet && !opts.check {
+        info!("converting from {} to {}", &from_path.display(), &to_path.display());
+    }
+    check_output_path(to_path, opts)?;
+    let start = Instant::now();
+    let content = read_input(from_path, opts)?;
+    timings.read = start.elapsed();
+    let start = Instant::now();
+    let docs = parse(&content, from_path, opts)?;
+    timings.parse = start.elapsed();
+    if let (InputFormat::Yaml, true) = (opts.input_format(), opts.expand_anchors) {
+        let aliases = yaml_aliases(&content);
+        eprintln!("{}: expanded {} aliases", from_path.display(), aliases.len());
+        for (line, name) in aliases {
+            eprintln!("  line {}: *{}", line, name);
+        }
+    }
+    let start = Instant::now();
+    let docs = prepare_output(docs, from_path, opts)?;
+    if opts.verify {
+        verify(&docs, opts).map_err(|e| e.with_path(from_path))?;
+    }
+    timings.prepare = start.elapsed();
+    if opts.stats_tree {
+        eprintln!("{}: {}", from_path.display(), TreeStats::of(&docs));
+    }
+    if opts.size_report {
+        let mut counter = ByteCount(0);
+        serialize(&docs, opts, &mut counter).map_err(|e| e.with_path(from_path))?;
+        let ratio = if content.is_empty() { 0.0 } else { counter.0 as f64 / content.len() as f64 };
+        eprintln!("{}: {} bytes in, {} bytes out, {:.2}x", from_path.display(), content.len(), counter.0, ratio);
+    }
+    if opts.skip_unchanged && !to_stdout && !opts.dry_run && !opts.check && to_path.exists() {
+        if is_unchanged(&docs, from_path, to_path, opts)? {
+            if !opts.quiet {
+                info!("{} is unchanged, leaving it alone", to_path.display());
+            }
+            timings.unchanged = true;
+            return Ok(timings);
+        }
+        if !opts.force {
+            return Err(Error::Io(format!("outfile already exists, use --force to overwrite it\n{}", to_path.display())))
+        }
+    }
+    let start = Instant::now();
+    write_output(&docs, from_path, to_path, opts)?;
+    timings.write = start.elapsed();
+    if let (InputFormat::Yaml, CommentHandling::Sidecar) = (opts.input_format(), opts.comments) {
+        if !opts.dry_run && !opts.check && !to_stdout {
+            write_comments(&content, to_path)?;
+        }
+    }
+    if opts.time {
+        eprintln!("{}: {}", from_path.display(), timings);
+    }
+    Ok(timings)
+}
+
+/// Convert each `---` separated yaml document from
+/// `from_path` as soon as it has been read, writing it to
+/// `to_path` as a line of json. This is meant for pipes that
+/// keep producing documents, serde_yaml's reader waits for
+/// the end of its input before parsing so lines are collected
+/// here until the next `---` or `...` instead. With
+/// `opts.keep_going` a document that fails is reported and
+/// skipped
+pub fn convert_follow(from_path: &Path, to_path: &Path, opts: &Options) -> Result<(), Error> {
+    if let InputFormat::Json = opts.input_format() {
+        return Err(Error::Io("--follow only reads yaml".into()));
+    }
+    check_output_path(to_path, opts)?;
+    let input: Box<dyn BufRead> = if is_std_stream(from_path) {
+        Box::new(BufReader::new(stdin()))
+    } else {
+        Box::new(BufReader::new(File::open(from_path).map_err(|e| Error::Io(format!("Failed to read {}: {}", from_path.display(), e)))?))
+    };
+    let mut out: Box<dyn Write> = if is_std_stream(to_path) {
+        Box::new(stdout())
+    } else {
+        Box::new(File::create(to_path).map_err(|e| write_error(to_path, &e))?)
+    };
+    let opts = Options { ndjson: true, ..opts.clone() };
+    let mut doc = String::new();
+    let mut count = 0;
+    for line in input.lines() {
+        let line = line.map_err(|e| Error::Io(format!("Failed to read {}: {}", from_path.display(), e)))?;
+        let trimmed = line.trim_end();
+        if trimmed == "---" || trimmed == "..." || trimmed.starts_with("--- ") {
+            follow_document(&doc, &mut count, from_path, &mut out, &opts)?;
+            doc.clear();
+            // `--- value` starts a document on the same line
+            if let Some(rest) = trimmed.strip_prefix("--- ") {
+                doc.push_str(rest);
+                doc.push('\n');
+            }
+            continue;
+        }
+        doc.push_str(&line);
+        doc.push('\n');
+    }
+    follow_document(&doc, &mut count, from_path, &mut out, &opts)
+}
+
+/// Convert and write one document read by `convert_follow`,
+/// documents that are only whitespace and comments are skipped
+fn follow_document(doc: &str, count: &mut usize, from_path: &Path, out: &mut dyn Write, opts: &Options) -> Result<(), Error> {
+    if doc.lines().all(|line| line.trim().is_empty() || line.trim_start().starts_with('#')) {
+        return Ok(());
+    }
+    *count += 1;
+    let res = parse(doc, from_path, opts)
+        .and_then(|docs| prepare_output(docs, from_path, opts))
+        .and_then(|docs| {
+            serialize(&docs, opts, &mut *out)?;
+            out.write_all(b"\n")?;
+            out.flush()?;
+            Ok(())
+        });
+    match res {
+        Err(e) if opts.keep_going => {
+            error!("document {} of {}: {}", count, from_path.display(), e);
+            Ok(())
+        },
+        Err(e) => Err(Error::Invalid(format!("document {}: {}", count, e))),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Re-serialize `path` in its own format, replacing the file.
+/// The file is always written atomically, so a failure leaves
+/// the original alone
+pub fn convert_in_place(path: &Path, opts: &Options) -> Result<(), Error> {
+    if is_std_stream(path) {
+        return Err(Error::Io("stdin can't be converted in place".into()));
+    }
+    if opts.front_matter {
+        return Err(Error::Io("front matter can't be converted in place".into()));
+    }
+    let format = opts.input_format();
+    let opts = Options {
+        direction: match format {
+            InputFormat::Yaml => Direction::JsonToYaml,
+            InputFormat::Json => Direction::YamlToJson,
+        },
+        output_format: OutputFormat::Json,
+        in_format: Some(format),
+        annotate_source: false,
+        template: None,
+        ..opts.clone()
+    };
+    if opts.dry_run {
+        println!("would reformat {}", path.display());
+    } else if !opts.quiet {
+        info!("reformatting {}", path.display());
+    }
+    let content = read_input(path, &opts)?;
+    let docs = parse(&content, path, &opts)?;
+    write_output(&prepare_output(docs, path, &opts)?, path, path, &Options { atomic: true, ..opts })
+}
+
+/// Deep merge each of the files in `from_paths` into a single
+/// document and convert that, later files win over earlier
+/// ones. See `transform::merge`
+pub fn convert_merge(from_paths: &[PathBuf], to_path: &Path, opts: &Options) -> Result<(), Error> {
+    let names = from_paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ");
+    if opts.dry_run {
+        println!("would merge {} into {}", names, to_path.display());
+    } else if !is_std_stream(to_path) && !opts.quiet {
+        info!("merging {} into {}", names, to_path.display());
+    }
+    check_output_path(to_path, opts)?;
+    let mut merged: Option<Value> = None;
+    for from_path in from_paths {
+        let content = read_input(from_path, opts)?;
+        let value = collect_documents(parse(&content, from_path, opts)?);
+        merged = Some(match merged {
+            Some(base) => transform::merge(base, value, opts.merge_arrays),
+            None => value,
+        });
+    }
+    // a merged document has no single source to annotate
+    let opts = &Options { annotate_source: false, ..opts.clone() };
+    let docs = prepare_output(vec![merged.unwrap_or(Value::Null)], to_path, opts)?;
+    write_output(&docs, to_path, to_path, opts)
+}
+
+/// The shape of converted documents, `depth` is how deeply
+/// mappings and sequences are nested
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TreeStats {
+    pub mappings: usize,
+    pub sequences: usize,
+    pub scalars: usize,
+    pub depth: usize,
+}
+
+impl TreeStats {
+    /// Count every node of `docs`, the depth is that of the
+    /// deepest document
+    pub fn of(docs: &[Value]) -> TreeStats {
+        let mut stats = TreeStats::default();
+        for doc in docs {
+            stats.add(doc, 0);
+        }
+        stats
+    }
+
+    fn add(&mut self, value: &Value, depth: usize) {
+        match value {
+            Value::Mapping(map) => {
+                self.mappings += 1;
+                self.depth = self.depth.max(depth + 1);
+                for (_, v) in map {
+                    self.add(v, depth + 1);
+                }
+            },
+            Value::Sequence(seq) => {
+                self.sequences += 1;
+                self.depth = self.depth.max(depth + 1);
+                for v in seq {
+                    self.add(v, depth + 1);
+                }
+            },
+            Value::Tagged(tagged) => self.add(&tagged.value, depth),
+            _ => self.scalars += 1,
+        }
+    }
+}
+
+impl ::std::fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{} mappings, {} sequences, {} scalars, nested {} deep",
+            self.mappings, self.sequences, self.scalars, self.depth)
+    }
+}
+
+/// Counts the bytes written to it, for `opts.size_report`
+struct ByteCount(usize);
+
+impl Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> ::std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Make sure `to_path` can be written to
+fn check_output_path(to_path: &Path, opts: &Options) -> Result<(), Error> {
+    // a missing output is reported as out of date once the
+    // input has been converted
+    if is_std_stream(to_path) || opts.check {
+        return Ok(());
+    }
+    let to_dir = to_path.parent().ok_or(Error::Io("outfile doesn't have a parent".into()))?;
+    // a bare file name is in the current directory
+    let to_dir = if to_dir.as_os_str().is_empty() { Path::new(".") } else { to_dir };
+    // nested directories are only created when actually converting
+    if !to_dir.exists() && !opts.dry_run {
+        return Err(Error::Io(format!("outfile directory does not exists\n{}", to_path.display())))
+    }
+    // with skip_unchanged an identical output isn't overwritten,
+    // so it is only an error once the new output is known
+    if !opts.force && !opts.skip_unchanged && to_path.exists() {
+        return Err(Error::Io(format!("outfile already exists, use --force to overwrite it\n{}", to_path.display())))
+    }
+    Ok(())
+}
+
+/// Read all of `from_path`, or stdin when it is `-`
+fn read_input(from_path: &Path, opts: &Options) -> Result<String, Error> {
+    let bytes = if is_std_stream(from_path) {
+        let mut bytes = Vec::new();
+        stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else if let Some(url) = as_url(from_path) {
+        fetch(url)?
+    } else {
+        if !from_path.exists() {
+            return Err(Error::Io(format!("infile does not exist\n{}", from_path.display())))
+        }
+        retry(opts.retries, || read_file(from_path)).map_err(|e| Error::Io(format!("Failed to read {}: {}", from_path.display(), e)))?
+    };
+    let content = decode(bytes, from_path)?;
+    let content = if opts.front_matter {
+        front_matter(&content)
+            .map(str::to_string)
+            .ok_or_else(|| Error::DeError(format!("{}: no --- fenced front matter at the start of the file", from_path.display())))?
+    } else {
+        content
+    };
+    if content.trim().is_empty() && !opts.quiet {
+        warn!("{} is empty, converting it as null", from_path.display());
+    }
+    Ok(content)
+}
+
+/// An input starting with `http://` or `https://` is fetched
+/// instead of read from disk
+pub fn as_url(path: &Path) -> Option<&str> {
+    path.to_str().filter(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// Download the body of `url`, anything but a 2xx response is
+/// an error
+#[cfg(feature = "url")]
+fn fetch(url: &str) -> Result<Vec<u8>, Error> {
+    let res = ureq::get(url)
+        .timeout(Duration::from_secs(30))
+        .call()
+        .map_err(|e| match e {
+            ureq::Error::Status(code, res) => Error::Io(format!("Failed to fetch {}: the server responded {} {}", url, code, res.status_text())),
+            // the transport error already names the url
+            ureq::Error::Transport(e) => Error::Io(format!("Failed to fetch {}", e)),
+        })?;
+    let mut bytes = Vec::new();
+    res.into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|e| Error::Io(format!("Failed to fetch {}: {}", url, e)))?;
+    Ok(bytes)
+}
+
+#[cfg(not(feature = "url"))]
+fn fetch(url: &str) -> Result<Vec<u8>, Error> {
+    Err(Error::Io(format!("Failed to fetch {}: y2j was built without the url feature", url)))
+}
+
+/// Read the whole file, gzipped files are decompressed
+fn read_file(path: &Path) -> ::std::io::Result<Vec<u8>> {
+    if path.extension().map(|ext| ext == "gz").unwrap_or(false) {
+        let mut bytes = Vec::new();
+        GzDecoder::new(File::open(path)?).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        read(path)
+    }
+}
+
+/// Decode the contents of `path` as utf-8, dropping a leading
+/// byte order mark
+fn decode(mut bytes: Vec<u8>, path: &Path) -> Result<String, Error> {
+    if bytes.starts_with(&[0xFF, 0xFE]) || bytes.starts_with(&[0xFE, 0xFF]) {
+        return Err(Error::Io(format!("{} is utf-16 encoded, only utf-8 is supported", path.display())));
+    }
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        bytes.drain(..3);
+    }
+    String::from_utf8(bytes).map_err(|e| {
+        Error::Io(format!("{} is not valid utf-8, invalid byte at offset {}", path.display(), e.utf8_error().valid_up_to()))
+    })
+}
+
+/// Prepare and check the parsed documents from `source`, the
+/// result is what will be serialized
+fn prepare_output(docs: Vec<Value>, source: &Path, opts: &Options) -> Result<Vec<Value>, Error> {
+    if opts.ndjson {
+        docs.into_iter()
+            .map(|doc| prepare(doc, opts).map(|doc| annotate(doc, source, opts)))
+            .collect::<Result<Vec<Value>, Error>>()
+    } else {
+        prepare(collect_documents(docs), opts).map(|value| vec![annotate(value, source, opts)])
+    }.and_then(|docs| check_output(&docs, opts).map(|_| docs))
+        .map_err(|e| e.with_path(source))
+}
+
+/// With `opts.annotate_source` wrap a json document from
+/// `source` as `{"_source": source, "data": doc}`
+fn annotate(doc: Value, source: &Path, opts: &Options) -> Value {
+    if !opts.annotate_source || !matches!(opts.direction, Direction::YamlToJson) {
+        return doc;
+    }
+    let mut annotated = serde_yaml::Mapping::new();
+    annotated.insert(Value::String("_source".into()), Value::String(source.display().to_string()));
+    annotated.insert(Value::String("data".into()), doc);
+    Value::Mapping(annotated)
+}
+
+/// Serialize the prepared documents from `source` to `to_path`
+fn write_output(docs: &[Value], source: &Path, to_path: &Path, opts: &Options) -> Result<(), Error> {
+    if opts.dry_run {
+        return serialize(docs, opts, sink()).map_err(|e| e.with_path(source));
+    }
+    if opts.check {
+        return check_existing(docs, source, to_path, opts);
+    }
+    if is_std_stream(to_path) {
+        let out = stdout();
+        serialize(docs, opts, out.lock()).map_err(|e| e.with_path(source))?;
+        return Ok(());
+    }
+    // an atomic write leaves `to_path` in place until it is
+    // replaced, so the backup is a copy rather than a move
+    let backup = if opts.backup { backup(to_path, opts.atomic)? } else { None };
+    let res = if opts.atomic {
+        write_atomic(docs, source, to_path, opts)
+    } else {
+        retry(opts.retries, || File::create(to_path))
+            .map_err(|e| write_error(to_path, &e))
+            .and_then(|out| serialize(docs, opts, BufWriter::new(Retrying { inner: out, retries: opts.retries }))
+                .map_err(|e| {
+                    // don't leave a partially written file behind
+                    let _ = remove_file(to_path);
+                    e.with_path(source)
+                }))
+    };
+    if let (Err(_), Some(backup), false) = (&res, backup, opts.atomic) {
+        let _ = rename(backup, to_path);
+    }
+    res
+}
+
+/// Errors that may go away on their own, like a busy network
+/// file system
+fn is_transient(e: &::std::io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// Run `op` again up to `retries` times while it fails with a
+/// transient error, waiting a little longer before each try
+fn retry<T, F: FnMut() -> ::std::io::Result<T>>(retries: usize, mut op: F) -> ::std::io::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(ref e) if is_transient(e) && attempt < retries => {
+                ::std::thread::sleep(Duration::from_millis(50 << attempt));
+                attempt += 1;
+            },
+            res => return res,
+        }
+    }
+}
+
+/// A writer that retries each write and flush with `retry`
+struct Retrying<W> {
+    inner: W,
+    retries: usize,
+}
+
+impl<W: Write> Write for Retrying<W> {
+    fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+        let (inner, retries) = (&mut self.inner, self.retries);
+        retry(retries, || inner.write(buf))
+    }
+
+    fn flush(&mut self) -> ::std::io::Result<()> {
+        let (inner, retries) = (&mut self.inner, self.retries);
+        retry(retries, || inner.flush())
+    }
+}
+
+/// Move an existing `to_path` aside to `to_path.bak`, or copy
+/// it there when `keep` is set, replacing any older backup
+fn backup(to_path: &Path, keep: bool) -> Result<Option<PathBuf>, Error> {
+    if !to_path.exists() {
+        return Ok(None);
+    }
+    let mut name = to_path.as_os_str().to_owned();
+    name.push(".bak");
+    let backup = PathBuf::from(name);
+    if keep {
+        copy(to_path, &backup).map_err(|e| write_error(&backup, &e))?;
+    } else {
+        rename(to_path, &backup).map_err(|e| write_error(&backup, &e))?;
+    }
+    Ok(Some(backup))
+}
+
+/// Does `to_path` already hold exactly what would be written
+fn is_unchanged(docs: &[Value], source: &Path, to_path: &Path, opts: &Options) -> Result<bool, Error> {
+    let mut out = Vec::new();
+    serialize(docs, opts, &mut out).map_err(|e| e.with_path(source))?;
+    Ok(read(to_path).map(|existing| existing == out).unwrap_or(false))
+}
+
+/// Serialize into memory and compare the result to what is
+/// already in `to_path`
+fn check_existing(docs: &[Value], source: &Path, to_path: &Path, opts: &Options) -> Result<(), Error> {
+    if is_std_stream(to_path) {
+        return Err(Error::Io("--check needs an output file to compare against, not stdout".into()));
+    }
+    let mut out = Vec::new();
+    serialize(docs, opts, &mut out).map_err(|e| e.with_path(source))?;
+    match ::std::fs::read(to_path) {
+        Ok(existing) if existing == out => Ok(()),
+        Ok(_) => Err(Error::OutOfDate(vec![format!("{} is out of date", to_path.display())])),
+        Err(_) => Err(Error::OutOfDate(vec![format!("{} does not exist", to_path.display())])),
+    }
+}
+
+/// Write to a hidden temporary file next to `to_path` and
+/// rename it over `to_path` once it is synced to disk, so
+/// `to_path` is never seen half written
+fn write_atomic(docs: &[Value], source: &Path, to_path: &Path, opts: &Options) -> Result<(), Error> {
+    let file_name = to_path.file_name().ok_or(Error::Io(format!("{} is not a file", to_path.display())))?;
+    let tmp_path = to_path.with_file_name(format!(".{}.y2j-tmp", file_name.to_string_lossy()));
+    let out = retry(opts.retries, || File::create(&tmp_path)).map_err(|e| write_error(to_path, &e))?;
+    let mut out = BufWriter::new(Retrying { inner: out, retries: opts.retries });
+    let res = serialize(docs, opts, &mut out)
+        .map_err(|e| e.with_path(source))
+        .and_then(|_| out.into_inner().map_err(|e| Error::Io(format!("Failed to write {}: {}", tmp_path.display(), e))))
+        .and_then(|file| file.inner.sync_all().map_err(Error::from))
+        .and_then(|_| rename(&tmp_path, to_path).map_err(|e| Error::Io(format!("Failed to replace {}: {}", to_path.display(), e))));
+    if res.is_err() {
+        let _ = remove_file(&tmp_path);
+    }
+    res
+}
+
+/// The 1 based line and column of the first tab in the
+/// leading whitespace of a line of `yaml`. Block scalar content
+/// can start with tabs once it is indented with spaces, those
+/// tabs are part of the string
+pub fn tab_indent(yaml: &str) -> Option<(usize, usize)> {
+    // the indentation of the line a block scalar starts on and,
+    // once its first line is seen, the indentation of its content
+    let mut block: Option<(usize, Option<usize>)> = None;
+    for (i, line) in yaml.lines().enumerate() {
+        let spaces = line.len() - line.trim_start_matches(' ').len();
+        if let Some((parent, content)) = block {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let content = content.unwrap_or(spaces);
+            if spaces > parent && spaces >= content {
+                block = Some((parent, Some(content)));
+                continue;
+            }
+            block = None;
+        }
+        let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+        if let Some(column) = line[..indent].find('\t') {
+            return Some((i + 1, column + 1));
+        }
+        if is_block_header(line) {
+            block = Some((spaces, None));
+        }
+    }
+    None
+}
+
+/// Does `line` end with a `|` or `>` block scalar indicator,
+/// ignoring a trailing comment
+fn is_block_header(line: &str) -> bool {
+    let line = match line.find(" #") {
+        Some(i) => &line[..i],
+        None => line,
+    };
+    let line = line.trim_end().trim_end_matches(['-', '+', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9']);
+    let mut chars = line.chars().rev();
+    matches!(chars.next(), Some('|') | Some('>')) && chars.next().map(char::is_whitespace).unwrap_or(true)
+}
+
+/// The `---` fenced yaml block at the start of a markdown
+/// file, the rest of the file is ignored. The opening fence is
+/// kept so line numbers in parse errors match the file
+pub fn front_matter(text: &str) -> Option<&str> {
+    let mut lines = text.split_inclusive('\n');
+    let first = lines.next()?;
+    if first.trim_end() != "---" {
+        return None;
+    }
+    let mut end = first.len();
+    for line in lines {
+        if line.trim_end() == "---" {
+            return Some(&text[..end]);
+        }
+        end += line.len();
+    }
+    None
+}
+
+/// Write the comments from `yaml` next to `to_path`, nothing
+/// is written when there are no comments
+fn write_comments(yaml: &str, to_path: &Path) -> Result<(), Error> {
+    let comments = yaml_comments(yaml);
+    if comments.is_empty() {
+        return Ok(());
+    }
+    let mut sidecar = String::new();
+    for (line, comment) in comments {
+        sidecar.push_str(&format!("{}: {}\n", line, comment));
+    }
+    write(to_path.with_extension("comments.txt"), sidecar)?;
+    Ok(())
+}
+
+/// Collect every `#` comment in a yaml document along with its
+/// 1 based line number. This is a line by line scan that skips
+/// `#` inside of quotes, a `#` inside of a block scalar will
+/// be reported as a comment
+pub fn yaml_comments(yaml: &str) -> Vec<(usize, String)> {
+    let mut ret = Vec::new();
+    for (i, line) in yaml.lines().enumerate() {
+        let mut quote = None;
+        let mut prev = ' ';
+        for (idx, c) in line.char_indices() {
+            match (quote, c) {
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (Some(q), _) if q == c => quote = None,
+                (None, '#') if prev.is_whitespace() => {
+                    ret.push((i + 1, line[idx..].to_string()));
+                    break;
+                },
+                _ => (),
+            }
+            prev = c;
+        }
+    }
+    ret
+}
+
+/// Collect every `*alias` in a yaml document along with its
+/// 1 based line number. Like `yaml_comments` this is a line by
+/// line scan, quotes and comments are skipped
+pub fn yaml_aliases(yaml: &str) -> Vec<(usize, String)> {
+    let mut ret = Vec::new();
+    for (i, line) in yaml.lines().enumerate() {
+        let mut quote = None;
+        let mut prev = ' ';
+        // the last character that wasn't whitespace, an alias
+        // can only start a node
+        let mut last = None;
+        for (idx, c) in line.char_indices() {
+            match (quote, c) {
+                (None, '"') | (None, '\'') => quote = Some(c),
+                (Some(q), _) if q == c => quote = None,
+                (None, '#') if prev.is_whitespace() => break,
+                (None, '*') if (prev.is_whitespace() || "[{,".contains(prev))
+                    && last.map(|l| ":-?[{,".contains(l)).unwrap_or(true) => {
+                    let name = line[idx + 1..]
+                        .split(|c: char| c.is_whitespace() || ",[]{}".contains(c))
+                        .next()
+                        .unwrap_or_default();
+                    if !name.is_empty() {
+                        ret.push((i + 1, name.to_string()));
+                    }
+                },
+                _ => (),
+            }
+            if !c.is_whitespace() {
+                last = Some(c);
+            }
+            prev = c;
+        }
+    }
+    ret
+}
+
+/// serde_yaml drops the standard `!!binary` tag while parsing,
+/// swap it for the local `!binary` tag that is kept as a
+/// `Value::Tagged`. Only a `!!binary` where a node can start is
+/// swapped, anything inside of quotes, comments, plain scalars
+/// or block scalars is left alone
+fn keep_binary_tags(yaml: &str) -> String {
+    let mut ret = String::with_capacity(yaml.len());
+    // the indentation a block scalar's parent is at and, once
+    // its first line is seen, the indentation of its content
+    let mut block: Option<(usize, Option<usize>)> = None;
+    // if the next non-whitespace character starts a node
+    let mut node_start = true;
+    // quoted scalars can go over more than one line
+    let mut quote = None;
+    for line in yaml.lines() {
+        let indent = line.len() - line.trim_start_matches(' ').len();
+        if let Some((parent, content)) = block {
+            match content {
+                _ if line.trim().is_empty() => {
+                    ret.push_str(line);
+                    ret.push('\n');
+                    continue;
+                },
+                None if indent > parent => {
+                    block = Some((parent, Some(indent)));
+                    ret.push_str(line);
+                    ret.push('\n');
+                    continue;
+                },
+                Some(content) if indent >= content => {
+                    ret.push_str(line);
+                    ret.push('\n');
+                    continue;
+                },
+                _ => block = None,
+            }
+        }
+        let mut prev = ' ';
+        // if this line ends with a `|` or `>` block indicator
+        let mut header = false;
+        let mut chars = line.char_indices().peekable();
+        while let Some((idx, c)) = chars.next() {
+            let next = chars.peek().map(|(_, c)| *c).unwrap_or(' ');
+            match (quote, c) {
+                (None, '"') | (None, '\'') if node_start => quote = Some(c),
+                (Some('"'), '\\') | (Some('\''), '\'') if c == '\\' || next == '\'' => {
+                    // the escaped character can't end the string
+                    ret.push(c);
+                    if let Some((_, escaped)) = chars.next() {
+                        ret.push(escaped);
+                    }
+                    continue;
+                },
+                (Some(q), _) if q == c => quote = None,
+                (Some(_), _) => (),
+                (None, '#') if prev.is_whitespace() => {
+                    ret.push_str(&line[idx..]);
+                    break;
+                },
+                // a tag or anchor is followed by the node it belongs to
+                (None, '!') | (None, '&') if node_start => {
+                    let end = line[idx..].find(char::is_whitespace).map(|i| idx + i).unwrap_or(line.len());
+                    match &line[idx..end] {
+                        "!!binary" => ret.push_str("!binary"),
+                        prop => ret.push_str(prop),
+                    }
+                    while chars.peek().map(|(i, _)| *i < end).unwrap_or(false) {
+                        chars.next();
+                    }
+                    prev = 'y';
+                    continue;
+                },
+                (None, '|') | (None, '>') if node_start => header = true,
+                (None, _) if header && !c.is_whitespace() && !"-+0123456789".contains(c) => header = false,
+                _ => (),
+            }
+            if !c.is_whitespace() {
+                node_start = quote.is_none() && ("[{,".contains(c) || (":-?".contains(c) && next.is_whitespace()));
+            }
+            ret.push(c);
+            prev = c;
+        }
+        if header {
+            // a sequence entry that is only a block scalar is the
+            // parent, otherwise it is the key after the dashes
+            let entries = line[indent..].len() - line[indent..].trim_start_matches(['-', ' ']).len();
+            let dashes = line[indent..indent + entries].rfind('-').map(|i| indent + i);
+            let parent = match dashes {
+                Some(dash) if line[indent + entries..].starts_with(['|', '>', '!', '&']) => dash,
+                _ => indent + entries,
+            };
+            block = Some((parent, None));
+            node_start = false;
+        }
+        ret.push('\n');
+    }
+    ret
+}
+
+/// Parse each document in `content` according to the input
+/// format, errors are reported as `path:line:column: message`
+fn parse(content: &str, from_path: &Path, opts: &Options) -> Result<Vec<Value>, Error> {
+    if let (InputFormat::Yaml, true) = (opts.input_format(), opts.lint_tabs) {
+        if let Some((line, column)) = tab_indent(content) {
+            return Err(tab_error(from_path, line, column));
+        }
+    }
+    // serde_yaml's own message for a tab in the indentation
+    // points at the scalar around it or at the token after the
+    // tab rather than the tab
+    let yaml_error = |e: serde_yaml::Error| match (tab_indent(content), e.location()) {
+        (Some((line, column)), _) if e.to_string().contains("tab character") => tab_error(from_path, line, column),
+        (Some((line, column)), Some(loc)) if line == loc.line() => tab_error(from_path, line, column),
+        _ => yaml_error(from_path, &e),
+    };
+    match opts.input_format() {
+        InputFormat::Yaml if opts.decode_binary => yaml_documents(&keep_binary_tags(content), opts.strict_keys, opts.timestamps)
+            .map_err(yaml_error),
+        InputFormat::Yaml => yaml_documents(content, opts.strict_keys, opts.timestamps).map_err(yaml_error),
+        InputFormat::Json => json_document(content, opts.strict_keys)
+            .map(|value| vec![value])
+            .map_err(|e| json_error(from_path, &e)),
+    }
+}
+
+/// Apply each of the requested transformations and checks
+/// to a parsed document
+fn prepare(mut value: Value, opts: &Options) -> Result<Value, Error> {
+    if let Some(path) = &opts.select {
+        value = transform::select(value, path)?;
+    }
+    if opts.expand_env {
+        value = transform::expand_env(value, opts.env_default.as_deref())?;
+    }
+    if opts.decode_binary {
+        value = transform::decode_binary(value, opts.binary_as)?;
+    }
+    if let Direction::YamlToJson = opts.direction {
+        value = transform::tags(value, opts.tags)?;
+    }
+    if let KeyCase::Upper | KeyCase::Lower = opts.case_keys {
+        value = transform::case_keys(value, opts.case_keys)?;
+    }
+    if opts.flatten {
+        value = transform::flatten(value, &opts.flatten_sep, opts.flatten_arrays);
+    }
+    if opts.trim_strings {
+        value = transform::trim_strings(value);
+    }
+    if let NullAs::EmptyString | NullAs::Omit = opts.nulls {
+        value = transform::nulls(value, opts.nulls);
+    }
+    if opts.sort_keys {
+        value = transform::sort_keys(value);
+    }
+    if let Some(places) = opts.float_precision {
+        value = transform::round_floats(value, places);
+    }
+    if let NumberMode::AllFloats | NumberMode::AllStrings = opts.numbers {
+        value = transform::numbers(value, opts.numbers);
+    }
+    if opts.tabular {
+        check_tabular(&value)?;
+    }
+    if let Some(template) = &opts.template {
+        value = fill_template(template.clone(), &value);
+    }
+    if let Some(schema) = &opts.schema {
+        validate(&value, schema)?;
+    }
+    Ok(value)
+}
+
+/// Load and compile the json schema at `path`
+pub fn load_schema(path: &Path) -> Result<jsonschema::Validator, Error> {
+    let content = read_to_string(path).map_err(|e| Error::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+    let schema = serde_json::from_str(&content).map_err(|e| json_error(path, &e))?;
+    jsonschema::validator_for(&schema).map_err(|e| Error::DeError(format!("{}: Invalid json schema: {}", path.display(), e)))
+}
+
+/// The string in a template that is replaced by each
+/// converted document
+pub const TEMPLATE_PLACEHOLDER: &str = "__Y2J__";
+
+/// Load the json document at `path` that each converted
+/// document is wrapped in, it has to contain the
+/// `TEMPLATE_PLACEHOLDER` string somewhere
+pub fn load_template(path: &Path) -> Result<Value, Error> {
+    let content = read_to_string(path).map_err(|e| Error::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+    let template = json_document(&content, false).map_err(|e| json_error(path, &e))?;
+    if !has_placeholder(&template) {
+        return Err(Error::Invalid(format!("{}: the template doesn't contain \"{}\"", path.display(), TEMPLATE_PLACEHOLDER)));
+    }
+    Ok(template)
+}
+
+fn has_placeholder(template: &Value) -> bool {
+    match template {
+        Value::String(s) => s == TEMPLATE_PLACEHOLDER,
+        Value::Sequence(seq) => seq.iter().any(has_placeholder),
+        Value::Mapping(map) => map.values().any(has_placeholder),
+        Value::Tagged(tagged) => has_placeholder(&tagged.value),
+        _ => false,
+    }
+}
+
+/// Replace every placeholder in `template` with `doc`
+fn fill_template(template: Value, doc: &Value) -> Value {
+    match template {
+        Value::String(s) if s == TEMPLATE_PLACEHOLDER => doc.clone(),
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(|v| fill_template(v, doc)).collect()),
+        Value::Mapping(map) => Value::Mapping(map.into_iter().map(|(k, v)| (k, fill_template(v, doc))).collect()),
+        other => other,
+    }
+}
+
+/// Check a document against a json schema, listing every
+/// failure
+fn validate(value: &Value, schema: &jsonschema::Validator) -> Result<(), Error> {
+    let instance = serde_json::to_value(value)?;
+    let failures: Vec<String> = schema.iter_errors(&instance)
+        .map(|e| format!("{}: {}", e.instance_path(), e))
+        .collect();
+    if failures.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Invalid(format!("document does not match the schema\n{}", failures.join("\n"))))
+}
+
+/// Catch anything the output format can't represent before
+/// the output is opened, so a failure leaves an existing
+/// output file untouched
+fn check_output(docs: &[Value], opts: &Options) -> Result<(), Error> {
+    for doc in docs {
+        match (opts.direction, opts.output_format) {
+            (Direction::YamlToJson, OutputFormat::Toml) if !opts.ndjson => check_toml(doc)?,
+            (Direction::YamlToJson, _) => check_json(doc, "")?,
+            (Direction::JsonToYaml, _) => (),
+        }
+    }
+    Ok(())
+}
+
+/// Serialize the documents into memory and read them back in
+/// the output format, failing if they come out different
+fn verify(docs: &[Value], opts: &Options) -> Result<(), Error> {
+    let mut out = Vec::new();
+    serialize(docs, opts, &mut out)?;
+    let text = String::from_utf8(out).map_err(|e| Error::Invalid(format!("output is not valid utf-8: {}", e)))?;
+    let parsed = match (opts.direction, opts.output_format) {
+        _ if opts.ndjson => text.lines()
+            .map(|line| json_document(line, false))
+            .collect::<Result<Vec<Value>, _>>()?,
+        (Direction::YamlToJson, OutputFormat::Json) => vec![json_document(&text, false)?],
+        (Direction::YamlToJson, OutputFormat::Json5) => vec![json5::from_str(&text)
+            .map_err(|e| Error::Invalid(format!("output could not be read back: {}", e)))?],
+        (Direction::YamlToJson, OutputFormat::Toml) => vec![toml::from_str(&text)
+            .map_err(|e| Error::Invalid(format!("output could not be read back: {}", e)))?],
+        (Direction::JsonToYaml, _) => vec![collect_documents(yaml_documents(&text, false, false)?)],
+    };
+    let normalize = |doc: &Value| serde_json::to_value(doc).map_err(Error::from);
+    if parsed.len() != docs.len() {
+        return Err(Error::Invalid(format!("output has {} documents when read back, expected {}", parsed.len(), docs.len())));
+    }
+    let to_json = opts.ndjson || matches!((opts.direction, opts.output_format), (Direction::YamlToJson, OutputFormat::Json));
+    for (doc, read_back) in docs.iter().zip(&parsed) {
+        // json writes .nan and .inf as null
+        if let Some(path) = find_non_finite(doc, "").filter(|_| to_json) {
+            return Err(Error::Invalid(format!("output does not match the input when read back, json can't represent the number at {}",
+                if path.is_empty() { "the top level" } else { &path })));
+        }
+        if let Some(path) = find_difference(&normalize(doc)?, &normalize(read_back)?, "") {
+            return Err(Error::Invalid(format!("output does not match the input when read back, at {}",
+                if path.is_empty() { "the top level" } else { &path })));
+        }
+    }
+    Ok(())
+}
+
+/// Find the dotted path of the first place two documents differ
+fn find_difference(a: &serde_json::Value, b: &serde_json::Value, path: &str) -> Option<String> {
+    use serde_json::Value::{Array, Object};
+    match (a, b) {
+        (Array(a), Array(b)) if a.len() == b.len() => a.iter()
+            .zip(b)
+            .enumerate()
+            .find_map(|(i, (a, b))| find_difference(a, b, &format!("{}[{}]", path, i))),
+        (Object(a), Object(b)) if a.len() == b.len() => a.iter()
+            .find_map(|(k, v)| match b.get(k) {
+                Some(other) => find_difference(v, other, &join_key(path, k)),
+                None => Some(join_key(path, k)),
+            }),
+        (a, b) if a == b => None,
+        _ => Some(path.to_string()),
+    }
+}
+
+/// Stream the converted documents into `out` in the output
+/// format, with `opts.ndjson` each document is written as
+/// a line of json
+fn serialize<W: Write>(docs: &[Value], opts: &Options, mut out: W) -> Result<(), Error> {
+    if opts.ndjson {
+        for (i, doc) in docs.iter().enumerate() {
+            if i > 0 {
+                out.write_all(b"\n")?;
+            }
+            serde_json::to_writer(&mut out, doc)?;
+        }
+    } else {
+        for doc in docs {
+            match (opts.direction, opts.output_format) {
+                (Direction::YamlToJson, OutputFormat::Json) if opts.indent.is_some() => {
+                    let indent = opts.indent.as_deref().unwrap_or_default();
+                    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+                    serde::Serialize::serialize(doc, &mut serde_json::Serializer::with_formatter(&mut out, formatter))?
+                },
+                (Direction::YamlToJson, OutputFormat::Json) if opts.pretty => serde_json::to_writer_pretty(&mut out, doc)?,
+                (Direction::YamlToJson, OutputFormat::Json) => serde_json::to_writer(&mut out, doc)?,
+                (Direction::YamlToJson, OutputFormat::Toml) => out.write_all(to_toml(doc)?.as_bytes())?,
+                (Direction::YamlToJson, OutputFormat::Json5) => out.write_all(to_json5(doc).as_bytes())?,
+                (Direction::JsonToYaml, _) => match opts.yaml_style {
+                    YamlStyle::Block => serde_yaml::to_writer(&mut out, doc)?,
+                    YamlStyle::Flow => out.write_all(to_yaml_flow(doc)?.as_bytes())?,
+                },
+            }
+        }
+    }
+    // yaml and toml already end with a newline
+    let json = opts.ndjson || matches!((opts.direction, opts.output_format),
+        (Direction::YamlToJson, OutputFormat::Json) | (Direction::YamlToJson, OutputFormat::Json5));
+    if opts.trailing_newline && json {
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// A path of `-` stands in for stdin/stdout
+pub fn is_std_stream(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Parse any valid YAML document, mappings, sequences
+/// and scalars alike. When the input contains more than
+/// one `---` separated document they are collected into
+/// a sequence, an empty input is `null`
+pub fn from_yaml(yaml: &str) -> Result<Value, serde_yaml::Error> {
+    Ok(collect_documents(from_yaml_documents(yaml)?))
+}
+
+/// Parse each `---` separated document in `yaml`, a repeated
+/// key replaces the earlier value
+pub fn from_yaml_documents(yaml: &str) -> Result<Vec<Value>, serde_yaml::Error> {
+    yaml_documents(yaml, false, false)
+}
+
+/// Parse each document in `yaml`, with `strict` a repeated key
+/// is an error and with `timestamps` timestamp values are
+/// rewritten as ISO-8601 UTC strings. `<<` merge keys are
+/// resolved, keys already in the mapping win over the merged ones
+fn yaml_documents(yaml: &str, strict: bool, timestamps: bool) -> Result<Vec<Value>, serde_yaml::Error> {
+    let keys = Keys { strict, timestamps: Some(yaml).filter(|_| timestamps) };
+    serde_yaml::Deserializer::from_str(yaml)
+        .map(|doc| {
+            let mut value = keys.deserialize(doc)?;
+            value.apply_merge()?;
+            Ok(value)
+        })
+        .collect()
+}
+
+/// A single document stands alone, anything else becomes
+/// a sequence of documents
+fn collect_documents(mut docs: Vec<Value>) -> Value {
+    match docs.len() {
+        0 => Value::Null,
+        1 => docs.remove(0),
+        _ => Value::Sequence(docs),
+    }
+}
+
+/// Mapping keys are written in the same order they
+/// appeared in the source document
+pub fn to_json(value: &Value, pretty: bool) -> Result<String, serde_json::Error> {
+    if pretty {
+        serde_json::to_string_pretty(value)
+    } else {
+        serde_json::to_string(value)
+    }
+}
+
+/// An empty input is `null`, matching `from_yaml`
+pub fn from_json(json: &str) -> Result<Value, serde_json::Error> {
+    json_document(json, false)
+}
+
+/// Parse `json`, with `strict` a repeated key is an error
+fn json_document(json: &str, strict: bool) -> Result<Value, serde_json::Error> {
+    if json.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    let mut de = serde_json::Deserializer::from_str(json);
+    let value = Keys { strict, timestamps: None }.deserialize(&mut de)?;
+    de.end()?;
+    Ok(value)
+}
+
+pub fn to_yaml(value: &Value) -> Result<String, serde_yaml::Error> {
+    serde_yaml::to_string(value)
+}
+
+/// Write `value` as yaml in flow style, on a single line with
+/// mappings in `{}` and sequences in `[]`. Scalars are written
+/// as serde_yaml would write them, strings that would need a
+/// block scalar or contain a flow indicator or `:` are double
+/// quoted
+pub fn to_yaml_flow(value: &Value) -> Result<String, serde_yaml::Error> {
+    let mut out = String::new();
+    write_yaml_flow(value, &mut out)?;
+    out.push('\n');
+    Ok(out)
+}
+
+fn write_yaml_flow(value: &Value, out: &mut String) -> Result<(), serde_yaml::Error> {
+    match value {
+        Value::Sequence(seq) => {
+            out.push('[');
+            for (i, v) in seq.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_yaml_flow(v, out)?;
+            }
+            out.push(']');
+        },
+        Value::Mapping(map) => {
+            out.push('{');
+            for (i, (k, v)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_yaml_flow(k, out)?;
+                out.push_str(": ");
+                write_yaml_flow(v, out)?;
+            }
+            out.push('}');
+        },
+        Value::Tagged(tagged) => {
+            out.push_str(&tagged.tag.to_string());
+            out.push(' ');
+            write_yaml_flow(&tagged.value, out)?;
+        },
+        Value::String(s) => {
+            let plain = serde_yaml::to_string(value)?;
+            let plain = plain.trim_end_matches('\n');
+            // a `:` next to a flow indicator or at the start ends
+            // a plain scalar inside of flow collections
+            if plain.contains(['\n', ',', '[', ']', '{', '}', ':']) || plain.starts_with('?') {
+                out.push_str(&json5_string(s));
+            } else {
+                out.push_str(plain);
+            }
+        },
+        _ => out.push_str(serde_yaml::to_string(value)?.trim_end_matches('\n')),
+    }
+    Ok(())
+}
+
+/// TOML has no null and every document must be a table, so
+/// both are reported before handing the value to the toml
+/// serializer
+pub fn to_toml(value: &Value) -> Result<String, Error> {
+    check_toml(value)?;
+    Ok(toml::to_string(value)?)
+}
+
+/// Write `value` as JSON5 meant for editing by hand. Output is
+/// always indented by two spaces, keys that are valid
+/// identifiers are left unquoted, every entry ends with a
+/// comma and nan and infinite numbers are written as `NaN` and
+/// `Infinity`. Tags are written the same way they are in json,
+/// as a mapping from the tag to the value. Comments are not
+/// carried over
+pub fn to_json5(value: &Value) -> String {
+    let mut out = String::new();
+    write_json5(value, 0, &mut out);
+    out
+}
+
+fn write_json5(value: &Value, indent: usize, out: &mut String) {
+    let pad = "  ".repeat(indent + 1);
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) if n.is_nan() => out.push_str("NaN"),
+        Value::Number(n) if n.is_infinite() => out.push_str(if n.as_f64().map(|f| f < 0.0).unwrap_or(false) { "-Infinity" } else { "Infinity" }),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) => out.push_str(&json5_string(s)),
+        Value::Sequence(seq) if seq.is_empty() => out.push_str("[]"),
+        Value::Sequence(seq) => {
+            out.push_str("[\n");
+            for v in seq {
+                out.push_str(&pad);
+                write_json5(v, indent + 1, out);
+                out.push_str(",\n");
+            }
+            out.push_str(&"  ".repeat(indent));
+            out.push(']');
+        },
+        Value::Mapping(map) if map.is_empty() => out.push_str("{}"),
+        Value::Mapping(map) => {
+            out.push_str("{\n");
+            for (k, v) in map {
+                out.push_str(&pad);
+                out.push_str(&json5_key(&key_name(k)));
+                out.push_str(": ");
+                write_json5(v, indent + 1, out);
+                out.push_str(",\n");
+            }
+            out.push_str(&"  ".repeat(indent));
+            out.push('}');
+        },
+        Value::Tagged(tagged) => {
+            out.push_str("{\n");
+            out.push_str(&pad);
+            out.push_str(&json5_string(&tagged.tag.to_string()));
+            out.push_str(": ");
+            write_json5(&tagged.value, indent + 1, out);
+            out.push_str(",\n");
+            out.push_str(&"  ".repeat(indent));
+            out.push('}');
+        },
+    }
+}
+
+/// Keys that are identifiers don't need quotes in JSON5
+fn json5_key(key: &str) -> String {
+    let mut chars = key.chars();
+    let is_ident = chars.next().map(|c| c.is_ascii_alphabetic() || c == '_' || c == '$').unwrap_or(false)
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+    if is_ident {
+        key.to_string()
+    } else {
+        json5_string(key)
+    }
+}
+
+fn json5_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_default()
+}
+
+fn check_toml(value: &Value) -> Result<(), Error> {
+    if !value.is_mapping() {
+        return Err(Error::SerError(format!("Serialization Error: toml documents must be a mapping at the top level, found {}", kind_of(value))));
+    }
+    if let Some(path) = find_null(value, "") {
+        return Err(Error::SerError(format!("Serialization Error: toml cannot represent the null at {}", path)));
+    }
+    Ok(())
+}
+
+/// json object keys have to be strings, numbers and booleans
+/// are written as strings but anything else fails
+fn check_json(value: &Value, path: &str) -> Result<(), Error> {
+    match value {
+        Value::Sequence(seq) => {
+            for (i, v) in seq.iter().enumerate() {
+                check_json(v, &format!("{}[{}]", path, i))?;
+            }
+        },
+        Value::Mapping(map) => {
+            for (k, v) in map {
+                let key = key_name(k);
+                match k {
+                    Value::String(_) | Value::Number(_) | Value::Bool(_) => (),
+                    _ => return Err(Error::SerError(format!("Serialization Error: json keys must be strings, found {} key {} at {}", kind_of(k), key, if path.is_empty() { "the top level" } else { path }))),
+                }
+                check_json(v, &join_key(path, &key))?;
+            }
+        },
+        Value::Tagged(tagged) => check_json(&tagged.value, path)?,
+        _ => (),
+    }
+    Ok(())
+}
+
+/// A table is a sequence of mappings that all have the same
+/// keys, in any order
+fn check_tabular(value: &Value) -> Result<(), Error> {
+    let rows = match value {
+        Value::Sequence(rows) => rows,
+        other => return Err(Error::Invalid(format!("tabular documents must be a sequence of mappings, found {}", kind_of(other)))),
+    };
+    let mut columns: Option<Vec<String>> = None;
+    for (i, row) in rows.iter().enumerate() {
+        let mut keys = match row {
+            Value::Mapping(map) => map.keys().map(key_name).collect::<Vec<_>>(),
+            other => return Err(Error::Invalid(format!("row {} is {}, every row must be a mapping", i, kind_of(other)))),
+        };
+        keys.sort();
+        match &columns {
+            Some(columns) if *columns != keys => {
+                let missing = columns.iter().filter(|k| !keys.contains(k)).cloned().collect::<Vec<_>>();
+                let extra = keys.iter().filter(|k| !columns.contains(k)).cloned().collect::<Vec<_>>();
+                return Err(Error::Invalid(format!("row {} doesn't have the same keys as row 0, missing [{}] and extra [{}]",
+                    i, missing.join(", "), extra.join(", "))));
+            },
+            Some(_) => (),
+            None => columns = Some(keys),
+        }
+    }
+    Ok(())
+}
+
+/// Find the dotted path of the first null in a document
+fn find_null(value: &Value, path: &str) -> Option<String> {
+    match value {
+        Value::Null => Some(path.to_string()),
+        Value::Sequence(seq) => seq.iter()
+            .enumerate()
+            .find_map(|(i, v)| find_null(v, &format!("{}[{}]", path, i))),
+        Value::Mapping(map) => map.iter()
+            .find_map(|(k, v)| find_null(v, &join_key(path, &key_name(k)))),
+        Value::Tagged(tagged) => find_null(&tagged.value, path),
+        _ => None,
+    }
+}
+
+/// Find the dotted path of the first nan or infinite number
+fn find_non_finite(value: &Value, path: &str) -> Option<String> {
+    match value {
+        Value::Number(n) if n.is_nan() || n.is_infinite() => Some(path.to_string()),
+        Value::Sequence(seq) => seq.iter()
+            .enumerate()
+            .find_map(|(i, v)| find_non_finite(v, &format!("{}[{}]", path, i))),
+        Value::Mapping(map) => map.iter()
+            .find_map(|(k, v)| find_non_finite(v, &join_key(path, &key_name(k)))),
+        Value::Tagged(tagged) => find_non_finite(&tagged.value, path),
+        _ => None,
+    }
+}
+
+fn join_key(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn kind_of(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Sequence(_) => "a sequence",
+        Value::Mapping(_) => "a mapping",
+        Value::Tagged(_) => "a tagged value",
+    }
+}
+
+/// Convert a YAML document into compact JSON
+pub fn yaml_to_json(yaml: &str) -> Result<String, Error> {
+    let value = from_yaml(yaml)?;
+    Ok(to_json(&value, false)?)
+}
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum Error {
+    SerError(String),
+    DeError(String),
+    Io(String),
+    /// A converted document failed a check
+    Invalid(String),
+    /// Every failure from a directory conversion
+    Batch(Vec<Error>),
+    /// Directory or glob mode found nothing to convert and
+    /// `error_on_empty` was set
+    NoMatches(String),
+    /// Every output `check` found out of date or missing, one
+    /// message each
+    OutOfDate(Vec<String>),
+}
+
+/// Describe a yaml parse failure as `path:line:column: message`
+fn yaml_error(path: &Path, e: &serde_yaml::Error) -> Error {
+    match e.location() {
+        Some(loc) => {
+            // serde_yaml ends its message with the same location,
+            // any other location it mentions is kept
+            let msg = e.to_string().replacen(&format!(" at line {} column {}", loc.line(), loc.column()), "", 1);
+            Error::DeError(format!("{}:{}:{}: {}", path.display(), loc.line(), loc.column(), msg))
+        },
+        None => Error::DeError(format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn tab_error(path: &Path, line: usize, column: usize) -> Error {
+    Error::DeError(format!("{}:{}:{}: a tab is used for indentation, yaml only allows spaces", path.display(), line, column))
+}
+
+/// Describe a failure to write `path`, permission errors
+/// suggest the likely cause
+fn write_error(path: &Path, e: &::std::io::Error) -> Error {
+    match e.kind() {
+        ErrorKind::PermissionDenied => Error::Io(format!("Permission denied writing {}, check that it and its directory aren't read only", path.display())),
+        _ => Error::Io(format!("Failed to write {}: {}", path.display(), e)),
+    }
+}
+
+/// Describe a json parse failure as `path:line:column: message`
+fn json_error(path: &Path, e: &serde_json::Error) -> Error {
+    Error::DeError(format!("{}:{}:{}: {}", path.display(), e.line(), e.column(), e))
+}
+
+impl Error {
+    /// Prefix a serialization or deserialization error with
+    /// the file it came from
+    fn with_path(self, path: &Path) -> Error {
+        match self {
+            Error::SerError(msg) => Error::SerError(format!("{}: {}", path.display(), msg)),
+            Error::DeError(msg) => Error::DeError(format!("{}: {}", path.display(), msg)),
+            Error::Invalid(msg) => Error::Invalid(format!("{}: {}", path.display(), msg)),
+            other => other,
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(other: serde_yaml::Error) -> Self {
+        Error::DeError(format!("Deserialization Error: {:?}", other))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(other: serde_json::Error) -> Self {
+        Error::SerError(format!("Serialization Error: {:?}", other))
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(other: toml::de::Error) -> Self {
+        Error::DeError(format!("Deserialization Error: {}", other))
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(other: toml::ser::Error) -> Self {
+        Error::SerError(format!("Serialization Error: {}", other))
+    }
+}
+
+impl From<::std::io::Error> for Error {
+    fn from(other: ::std::io::Error) -> Self {
+        Error::Io(format!("I/O Error: {:?}", other))
+    }
+}
+
+#[cfg(feature = "dir")]
+impl From<walkdir::Error> for Error {
+    fn from(other: walkdir::Error) -> Self {
+        Error::Io(format!("I/O Error: {:?}", other))
+    }
+}
+
+#[cfg(feature = "dir")]
+impl From<notify::Error> for Error {
+    fn from(other: notify::Error) -> Self {
+        Error::Io(format!("I/O Error: {:?}", other))
+    }
+}
+
+impl ::std::error::Error for Error {
+    fn description(&self) -> &str {
+        match self {
+            Error::SerError(msg) => msg,
+            Error::DeError(msg) => msg,
+            Error::Io(msg) => msg,
+            Error::Invalid(msg) => msg,
+            Error::Batch(_) => "multiple files failed to convert",
+            Error::NoMatches(msg) => msg,
+            Error::OutOfDate(_) => "outputs are out of date",
+        }
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let msg = match self {
+            Error::SerError(msg) => msg,
+            Error::DeError(msg) => msg,
+            Error::Io(msg) => msg,
+            Error::Invalid(msg) => msg,
+            Error::NoMatches(msg) => msg,
+            Error::OutOfDate(msgs) => return msgs.join("\n").fmt(f),
+            Error::Batch(errors) => {
+                write!(f, "{} files failed to convert", errors.len())?;
+                for e in errors {
+                    write!(f, "\n{}", e)?;
+                }
+                return Ok(());
+            },
+        };
+        msg.fmt(f)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Notes {
+    pub title: String,
+    pub notes: Option<Vec<Notes>>
+}
+
+impl Notes {
+    pub fn from_yaml(yaml: &str) -> Result<Notes, serde_yaml::Error> {
+        serde_yaml::from_str(yaml)
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self)
+    }
+}
\ No newline at end of file
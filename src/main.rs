@@ -1,164 +1,684 @@
 //! Read in a YAML file and output a JSON file
 extern crate docopt;
+extern crate env_logger;
+extern crate glob;
+#[macro_use]
+extern crate log;
+extern crate owo_colors;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
-extern crate serde_json;
-extern crate serde_yaml;
-extern crate walkdir;
+extern crate toml;
+extern crate y2j;
 
 use std::{
-    fs::{read_to_string, write},
-    path::{PathBuf},
+    collections::HashSet,
+    env,
+    fmt::Display,
+    fs::read_to_string,
+    io::{stderr, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 use docopt::{Docopt};
+use glob::Pattern;
+use log::{Level, LevelFilter};
+use owo_colors::OwoColorize;
+use y2j::{BinaryAs, combine_dir, convert, Error, convert_dir, convert_follow, convert_files, convert_glob, convert_in_place, convert_list, convert_merge, is_std_stream, CommentHandling, Direction, InputFormat, KeyCase, NullAs, NumberMode, Options, OutputFormat, TagHandling, YamlStyle, load_schema, load_template, watch_dir};
 
 const HELP: &str = r#"
-y2j (yaml to json) is a utility for converting yaml files into json files
+y2j (yaml to json) is a utility for converting yaml files into json files,
+or json files back into yaml files with --reverse
 
 Usage:
-    y2j -f | --file <inpath> <outpath>
-    y2j -d | --dir <inpath> <outpath>
+    y2j (-f | --file) [options] <inpaths>...
+    y2j (-d | --dir) [options] [--exclude=<glob>]... <inpath> <outpath>
+    y2j (-d | --dir) --beside [options] [--exclude=<glob>]... <inpath>
+    y2j (-g | --glob) [options] <pattern> <outpath>
+    y2j (-d | --dir) --count-only [options] [--exclude=<glob>]... <inpath>
+    y2j (-g | --glob) --count-only [options] <pattern>
+    y2j (-m | --merge) [options] <inpaths>... <outpath>
+    y2j --from-file=<list> [options] [<outpath>]
     y2j -h | --help
     y2j -v | --version
 
+In file mode the last path is the <outpath>, when it is a directory or
+more than one file is given each file is written into it with a name
+matching its input. Passing - as the input of a single file reads from
+stdin and passing - as the <outpath> writes to stdout. When built with
+the url feature an input may also be an http:// or https:// url. In
+directory mode an <outpath> of - writes every file to stdout as one json
+array, or as a line each with --ndjson.
+
+Defaults for any of the options can be set in a .y2jrc file in the
+current directory, either as toml or as key=value lines such as
+pretty=true or depth=2. In toml an option that can be repeated, like
+exclude, takes an array. Options passed on the command line win.
+
+Yaml comments can't be represented in json so they are dropped, unless
+using --comments-to sidecar which writes them next to each output file
+in a .comments.txt file.
+
 Options:
     -h, --help     Print this message
     -v, --version  Print the current version
-    -f, --file     Convert a single file
-    -d, --dir      Convert all .yaml or .yml files in a directory
+    -f, --file     Convert one or more files
+    -d, --dir      Convert all .yaml or .yml files in a directory, files
+                   ending in .gz are decompressed first
+    -g, --glob     Convert all files matching a glob pattern
+    --from-file=<list>  Convert each file listed in <list>, one path per line
+                        with blank lines and # comments ignored. Outputs
+                        go into <outpath> or next to each input
+    -m, --merge    Deep merge several files into one, later files win
+    --merge-arrays  When merging, concatenate sequences instead of
+                    replacing them
+    -p, --pretty   Pretty print the json output, indented with two spaces
+    --minify       Write the smallest json possible, with no whitespace and
+                   no trailing newline, numbers keep their type so 1.0
+                   stays 1.0. This can't be used with --pretty or --indent
+    --trailing-newline=<yn>  Whether json output ends with a newline, either
+                             yes or no [default: no]
+    --indent=<n>   Pretty print the json output indented with n spaces, or
+                   with tabs when n is tab
+    -r, --reverse  Convert json into yaml, in directory mode all .json files
+                   are converted into .yaml files
+    --reformat     Read json and write it back out as json, which honors
+                   the --pretty, --minify and --sort-keys options. In
+                   directory mode all .json files are reformatted
+    --combine      In directory mode write every file into <outpath> as one
+                   json array with an entry for each file
+    --combine-keyed  Like --combine but writes a json object keyed by the
+                     path of each file relative to <inpath>
+    --beside       In directory mode write each output next to its input,
+                   no <outpath> is given
+    --depth=<n>    How many directories deep to look for files in directory
+                   mode, nested files are written to the matching nested
+                   directory of <outpath> [default: 1]
+    --max-size=<bytes>  Skip files in directory mode that are larger than
+                        this, with a warning
+    --strip-components=<n>  Drop the first n directories of each file's path
+                            relative to <inpath> from its output path,
+                            files with fewer are written directly into
+                            <outpath> [default: 0]
+    --exclude=<glob>  Skip files in directory mode whose name or path relative
+                      to <inpath> matches the glob, can be given more
+                      than once
+    --follow-symlinks  Follow symlinks in directory mode, by default they are
+                       skipped. Links that loop back to a parent directory
+                       are always skipped
+    -q, --quiet    Only print errors, the same as --log-level error. When
+                   both are given --log-level wins
+    --log-level=<level>  The most detailed messages to print, one of error,
+                         warn, info, debug or trace, defaults to info
+    -F, --force    Overwrite output files that already exist
+    --skip-unchanged  Don't rewrite outputs that already hold exactly what
+                      would be written, they are counted as unchanged.
+                      Outputs that differ still need --force
+    --retries=<n>  How many times to retry reading or writing a file after a
+                   transient error like a timeout [default: 3]
+    --backup       Keep the previous version of each output that is
+                   overwritten as <output>.bak
+    --report=<path>  After converting many files write a json summary to
+                     <path> listing each input, output, status and error
+    --no-lock      Don't lock <outpath> in directory mode, by default a run
+                   waits for any other run into the same directory
+    --jobs=<n>     The number of files to convert at once in directory mode,
+                   defaults to the number of cores
+    --stats        After converting many files print how long it took, the
+                   files per second and how many files each thread did
+    --follow       In file mode keep reading the input, like a named pipe,
+                   and write each yaml document as a line of json as
+                   soon as the --- after it arrives
+    -i, --in-place  Reformat each file in its own format, writing it back
+                    to the same path, no <outpath> is given
+    --check        Convert in memory and compare the result to each existing
+                   output instead of writing it, every output that is out
+                   of date is listed and the exit code is 1
+    --dry-run      Check that every input parses and print where it would be
+                   written without writing anything
+    --count-only   In directory or glob mode print how many files would be
+                   converted and exit without converting them, no
+                   <outpath> is given
+    --output-format=<fmt>  The format to convert yaml into, either json,
+                           toml or json5. json5 is always indented, keys are
+                           only quoted when needed and every entry has a
+                           trailing comma [default: json]
+    --yml          Name the outputs of --reverse .yml instead of .yaml
+    --out-ext=<ext>  The extension given to each output file in directory
+                     or glob mode, defaults to one matching the output format
+    --tags=<mode>  What to do with yaml tags like !Foo, either strip to keep
+                   just the value, wrap to write an object with __tag__
+                   and value keys or error [default: strip]
+    --front-matter  Only convert the yaml front matter between the --- lines
+                    at the start of each markdown file, ignoring the
+                    rest of it. Use --match-ext md in directory mode
+    --yaml-style=<style>  The layout of yaml written with --reverse, either
+                          block or flow to write each document on one
+                          line [default: block]
+    --comments-to=<mode>  What to do with yaml comments, either drop or
+                          sidecar [default: drop]
+    --in-format=<fmt>  The format of the input, either yaml or json, defaults
+                       to yaml or to json with --reverse
+    --error-on-empty  Exit with code 2 when directory or glob mode finds no
+                      files to convert, by default this is a warning
+    --keep-going   Keep converting after a file fails, reporting every
+                   failure at the end
+    -w, --watch    In directory mode, keep running and convert each file
+                   again when it changes, this implies --force
+    --flatten      Flatten nested mappings into one mapping with dotted keys
+    --flatten-sep=<sep>  The separator used between flattened keys [default: .]
+    --flatten-arrays     Also flatten sequences, using each index as a key
+    --select=<path>  Only convert the part of each document at a dotted path
+                     like servers.0.name, numbers index into sequences
+    --case-keys=<case>  Rewrite every mapping key into upper or lower case,
+                        keys that end up the same are an error, or none
+                        to keep them as written [default: none]
+    --sort-keys    Sort the keys of every mapping alphabetically instead of
+                   keeping the order they were written in
+    --expand-env   Replace ${VAR} in string values with the environment
+                   variable VAR, failing when it isn't set
+    --env-default=<value>  Used in place of unset variables instead of
+                           failing when expanding them
+    --decode-binary  Decode yaml !!binary values, by default they are kept as
+                     their base64 text
+    --binary-as=<fmt>  How decoded binary values are written, either bytes
+                       for an array of numbers or base64 for a single
+                       base64 string [default: bytes]
+    --trim-strings  Trim the whitespace around string values, strings over
+                    more than one line keep their indentation and only
+                    lose trailing whitespace and blank lines at either end
+    --timestamps   Write yaml timestamps like 2024-01-02 or 2001-12-14
+                   21:59:43.10 -5 as ISO-8601 strings in UTC, like
+                   2024-01-02T00:00:00Z. Quoted strings are left alone
+    --null-as=<mode>  How nulls are written, either null, empty-string or
+                      omit to drop mapping entries that are null
+                      [default: null]
+    --number-mode=<mode>  How numbers are written, either preserve to keep
+                          integers and floats as parsed, all-floats or
+                          all-strings [default: preserve]
+    --float-precision=<n>  Round floats to at most n decimal places, this
+                           loses precision and doesn't change integers
+                           or strings
+    --atomic       Write each output to a temporary file that is renamed over
+                   the output once complete, so a crash never leaves a half
+                   written file behind
+    --verify       Read each output back and compare it to the input before
+                   it is written, failing when anything was lost
+    --time         Print how long reading, parsing, preparing and writing each
+                   file took, with a total in directory or glob mode
+    --stats-tree   Print how many mappings, sequences and scalars each file
+                   has and how deeply they nest
+    --size-report  Print the size in bytes of each input and its output and
+                   how they compare
+    --expand-anchors  Report each yaml alias that was replaced by a copy of
+                      its anchor, along with its line
+    --lint-tabs    Fail when a line of yaml is indented with a tab, even when
+                   it still parses
+    --strict-keys  Fail when a mapping repeats a key, by default the last
+                   value is kept
+    --annotate-source  Wrap each json document in an object with a _source key
+                       naming the file it came from and a data key
+                       holding the document
+    --ndjson       Write each yaml document as json on its own line instead of
+                   collecting multiple documents into an array, this can't
+                   be used with --reverse or --output-format
+    --tabular      Fail unless each document is a sequence of mappings that
+                   all have the same keys, like the rows of a table
+    --template=<path>  A json document that each converted document is
+                       written inside of, in place of the "__Y2J__"
+                       string in it
+    --schema=<path>  A json schema every converted document must match,
+                     files that don't match are not written
+    --match-ext=<exts>  A comma separated list of the file name suffixes
+                        converted in directory mode, the matched suffix
+                        is replaced by the output extension, defaults to
+                        yaml,yml or json with --reverse
 "#;
+#[derive(Deserialize, Clone, Copy)]
+enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+enum YesNo {
+    Yes,
+    No,
+}
+
 #[derive(Deserialize)]
 struct Opts {
+    pub flag_help: bool,
+    pub flag_version: bool,
     pub flag_file: bool,
     pub flag_dir: bool,
+    pub flag_glob: bool,
+    pub flag_merge: bool,
+    pub flag_from_file: Option<PathBuf>,
+    pub flag_merge_arrays: bool,
+    pub flag_combine: bool,
+    pub flag_combine_keyed: bool,
+    pub flag_pretty: bool,
+    pub flag_indent: Option<String>,
+    pub flag_minify: bool,
+    pub flag_float_precision: Option<u32>,
+    pub flag_trailing_newline: YesNo,
+    pub flag_reverse: bool,
+    pub flag_reformat: bool,
+    pub flag_depth: usize,
+    pub flag_max_size: Option<u64>,
+    pub flag_strip_components: usize,
+    pub flag_exclude: Vec<String>,
+    pub flag_follow_symlinks: bool,
+    pub flag_quiet: bool,
+    pub flag_log_level: Option<LogLevel>,
+    pub flag_output_format: OutputFormat,
+    pub flag_force: bool,
+    pub flag_jobs: Option<usize>,
+    pub flag_dry_run: bool,
+    pub flag_count_only: bool,
+    pub flag_check: bool,
+    pub flag_in_place: bool,
+    pub flag_follow: bool,
+    pub flag_beside: bool,
+    pub flag_yml: bool,
+    pub flag_out_ext: Option<String>,
+    pub flag_comments_to: CommentHandling,
+    pub flag_tags: TagHandling,
+    pub flag_front_matter: bool,
+    pub flag_skip_unchanged: bool,
+    pub flag_retries: usize,
+    pub flag_backup: bool,
+    pub flag_annotate_source: bool,
+    pub flag_stats: bool,
+    pub flag_no_lock: bool,
+    pub flag_report: Option<PathBuf>,
+    pub flag_tabular: bool,
+    pub flag_error_on_empty: bool,
+    pub flag_case_keys: KeyCase,
+    pub flag_stats_tree: bool,
+    pub flag_size_report: bool,
+    pub flag_yaml_style: YamlStyle,
+    pub flag_in_format: Option<InputFormat>,
+    pub flag_keep_going: bool,
+    pub flag_watch: bool,
+    pub flag_flatten: bool,
+    pub flag_flatten_sep: String,
+    pub flag_flatten_arrays: bool,
+    pub flag_select: Option<String>,
+    pub flag_sort_keys: bool,
+    pub flag_expand_env: bool,
+    pub flag_env_default: Option<String>,
+    pub flag_decode_binary: bool,
+    pub flag_binary_as: BinaryAs,
+    pub flag_trim_strings: bool,
+    pub flag_timestamps: bool,
+    pub flag_null_as: NullAs,
+    pub flag_number_mode: NumberMode,
+    pub flag_atomic: bool,
+    pub flag_verify: bool,
+    pub flag_time: bool,
+    pub flag_expand_anchors: bool,
+    pub flag_lint_tabs: bool,
+    pub flag_strict_keys: bool,
+    pub flag_ndjson: bool,
+    pub flag_template: Option<PathBuf>,
+    pub flag_schema: Option<PathBuf>,
+    pub flag_match_ext: Option<String>,
     pub arg_inpath: PathBuf,
+    pub arg_pattern: String,
+    pub arg_inpaths: Vec<PathBuf>,
     pub arg_outpath: PathBuf,
 }
 
-fn main() {
-    let args: Opts = Docopt::new(HELP)
-                .and_then(|d| d.deserialize())
-                .unwrap_or_else(|e| e.exit());
-    let res = if args.flag_file {
-        convert(&args.arg_inpath, &args.arg_outpath)
-    } else if args.flag_dir {
-        convert_dir(&args.arg_inpath, &args.arg_outpath)
-    } else { 
-        eprintln!("Error, you must use either the -f or -d flag when running");
-        println!("{}", HELP);
-        ::std::process::exit(1);
-    };
-    match res {
-        Ok(_) => {
-            println!("Successfully converted your files!")
-        },
-        Err(e) => {
-            eprintln!("Error converting your files {:?}", e);
-            println!("{}", HELP);
+/// The long options set in `.y2jrc` that aren't already part
+/// of `args`
+fn rc_args(args: &[String]) -> Result<Vec<String>, String> {
+    let path = Path::new(".y2jrc");
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let given = given_options(args);
+    let mut rc = Vec::new();
+    for (key, value) in parse_rc(&content)? {
+        let name = key.trim_start_matches('-').replace('_', "-");
+        if given.contains(&name) || value == "false" {
+            continue;
+        }
+        if value == "true" {
+            rc.push(format!("--{}", name));
+        } else {
+            rc.push(format!("--{}={}", name, value));
         }
     }
+    Ok(rc)
 }
 
-fn convert_dir(from_path: &PathBuf, to_path: &PathBuf) -> Result<(), Error> {
-    println!("Converting the files from {} to {}", from_path.display(), to_path.display());
-    for e in walkdir::WalkDir::new(&from_path).max_depth(1).min_depth(1) {
-        println!("entry: {:?}", e);
-        if let Ok(entry) = e {
-            if entry.file_type().is_file() {
-                let file_name = entry.file_name().to_string_lossy();
-                if file_name.ends_with(".yaml") || file_name.ends_with(".yml") {
-                    let mut target = entry.path().to_path_buf();
-                    target.set_extension("json");
-                    let new_name = target.file_name().ok_or(Error::Io("Failed to create an outfile with a .json extension".into()))?;
-                    convert(&entry.path().to_path_buf(), &to_path.join(&new_name))?;
-                }
+/// Parse a `.y2jrc` as toml, falling back to `key=value` lines
+fn parse_rc(content: &str) -> Result<Vec<(String, String)>, String> {
+    if let Ok(table) = content.parse::<toml::Table>() {
+        let mut pairs = Vec::new();
+        for (key, value) in table {
+            // an array gives the option once for each element
+            match value {
+                toml::Value::Array(values) => for value in values {
+                    pairs.push((key.clone(), rc_value(&key, value)?));
+                },
+                value => {
+                    let value = rc_value(&key, value)?;
+                    pairs.push((key, value));
+                },
             }
         }
+        return Ok(pairs);
     }
-    Ok(())
+    content.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.split_once('=') {
+            Some((key, value)) => Ok((key.trim().to_string(), value.trim().to_string())),
+            None => Err(format!("Invalid line in .y2jrc, expected key=value\n{}", line)),
+        })
+        .collect()
 }
 
-fn convert(from_path: &PathBuf, to_path: &PathBuf) -> Result<(), Error> {
-    println!("converting from {} to {}", &from_path.display(), &to_path.display());
-    if !from_path.exists() {
-        return Err(Error::Io(format!("infile does not exist\n{}", from_path.display())))
-    }
-    let to_dir = to_path.parent().ok_or(Error::Io("outfile doesn't have a parent".into()))?;
-    if !to_dir.exists() {
-        return Err(Error::Io(format!("outfile directory does not exists\n{}", to_path.display())))
+/// The text of a toml value from `.y2jrc`, only strings,
+/// numbers and booleans can be options
+fn rc_value(key: &str, value: toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        other => Err(format!("Invalid value for {} in .y2jrc, expected a string, number, boolean or an array of them\n{}", key, other)),
     }
-    let content = read_to_string(from_path)?;
-    let notes = Notes::from_yaml(&content)?;
-    let json = notes.to_json()?;
-    write(to_path, &json)?;
-    Ok(())
-}
-#[derive(Debug)]
-enum Error {
-    SerError(String),
-    DeError(String),
-    Io(String),
 }
 
-impl From<serde_yaml::Error> for Error {
-    fn from(other: serde_yaml::Error) -> Self {
-        Error::DeError(format!("Deserialization Error: {:?}", other))
+/// The long name of every option in `args`, short options
+/// are looked up in the options list of `HELP`
+fn given_options(args: &[String]) -> HashSet<String> {
+    let short_names = HELP.lines()
+        .map(str::trim)
+        .filter_map(|line| {
+            let mut chars = line.chars();
+            match (chars.next(), chars.next(), line.get(2..6)) {
+                (Some('-'), Some(short), Some(", --")) => line[6..].split(|c: char| c == '=' || c.is_whitespace())
+                    .next()
+                    .map(|long| (short, long.to_string())),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut given = HashSet::new();
+    for arg in args.iter().take_while(|arg| *arg != "--") {
+        if let Some(long) = arg.strip_prefix("--") {
+            given.insert(long.split('=').next().unwrap_or(long).to_string());
+        } else if let Some(shorts) = arg.strip_prefix('-') {
+            for short in shorts.chars() {
+                if let Some((_, long)) = short_names.iter().find(|(s, _)| *s == short) {
+                    given.insert(long.clone());
+                }
+            }
+        }
     }
+    given
 }
 
-impl From<serde_json::Error> for Error {
-    fn from(other: serde_json::Error) -> Self {
-        Error::SerError(format!("Serialization Error: {:?}", other))
-    }
+/// Print log messages to stderr without any decoration beyond
+/// a prefix for warnings and errors
+fn init_logger(level: LevelFilter) {
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format(|buf, record| match record.level() {
+            Level::Error => writeln!(buf, "error: {}", record.args()),
+            Level::Warn => writeln!(buf, "warning: {}", record.args()),
+            _ => writeln!(buf, "{}", record.args()),
+        })
+        .init();
 }
 
-impl From<::std::io::Error> for Error {
-    fn from(other: ::std::io::Error) -> Self {
-        Error::Io(format!("I/O Error: {:?}", other))
+/// Print `msg` to stderr after an `Error:` prefix, which is
+/// red when stderr is a terminal unless `plain` is set
+fn print_error(msg: &dyn Display, plain: bool) {
+    if plain || !stderr().is_terminal() {
+        eprintln!("Error: {}", msg);
+    } else {
+        eprintln!("{} {}", "Error:".red(), msg);
     }
 }
 
-impl From<walkdir::Error> for Error {
-    fn from(other: walkdir::Error) -> Self {
-        Error::Io(format!("I/O Error: {:?}", other))
+fn main() {
+    let mut argv: Vec<String> = env::args().collect();
+    // running without any arguments is asking what y2j does
+    if argv.len() == 1 {
+        println!("{}", HELP);
+        return;
     }
-}
-
-impl ::std::error::Error for Error {
-    fn description(&self) -> &str {
-        match self {
-            Error::SerError(msg) => msg,
-            Error::DeError(msg) => msg,
-            Error::Io(msg) => msg,
-        }
+    match rc_args(&argv[1..]) {
+        Ok(rc) => {
+            argv.splice(1..1, rc);
+        },
+        Err(e) => {
+            print_error(&format_args!("loading .y2jrc {}", e), false);
+            ::std::process::exit(1);
+        },
     }
-}
-
-impl ::std::fmt::Display for Error {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        let msg = match self {
-            Error::SerError(msg) => msg,
-            Error::DeError(msg) => msg,
-            Error::Io(msg) => msg,
-        };
-        msg.fmt(f)
+    let args: Opts = Docopt::new(HELP)
+                .and_then(|d| d.argv(argv).help(false).deserialize())
+                .unwrap_or_else(|e| e.exit());
+    if args.flag_help {
+        println!("{}", HELP);
+        return;
     }
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Notes {
-    title: String,
-    notes: Option<Vec<Notes>>
-}
-
-impl Notes {
-    pub fn from_yaml(yaml: &str) -> Result<Notes, serde_yaml::Error> {
-        serde_yaml::from_str(yaml)
+    if args.flag_version {
+        println!("y2j {}", env!("CARGO_PKG_VERSION"));
+        return;
     }
-
-    pub fn to_json(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string(&self)
+    let level = match (args.flag_log_level, args.flag_quiet) {
+        (Some(LogLevel::Error), _) | (None, true) => LevelFilter::Error,
+        (Some(LogLevel::Warn), _) => LevelFilter::Warn,
+        (Some(LogLevel::Info), _) | (None, false) => LevelFilter::Info,
+        (Some(LogLevel::Debug), _) => LevelFilter::Debug,
+        (Some(LogLevel::Trace), _) => LevelFilter::Trace,
+    };
+    init_logger(level);
+    let direction = if args.flag_reverse {
+        Direction::JsonToYaml
+    } else {
+        Direction::YamlToJson
+    };
+    let schema = match args.flag_schema {
+        Some(ref path) => match load_schema(path) {
+            Ok(schema) => Some(Arc::new(schema)),
+            Err(e) => {
+                print_error(&format_args!("loading the schema {}", e), args.flag_quiet);
+                ::std::process::exit(1);
+            },
+        },
+        None => None,
+    };
+    let template = match args.flag_template {
+        Some(ref path) => match load_template(path) {
+            Ok(template) => Some(template),
+            Err(e) => {
+                print_error(&format_args!("loading the template {}", e), args.flag_quiet);
+                ::std::process::exit(1);
+            },
+        },
+        None => None,
+    };
+    let exclude = match args.flag_exclude.iter().map(|glob| Pattern::new(glob)).collect::<Result<Vec<_>, _>>() {
+        Ok(exclude) => exclude,
+        Err(e) => {
+            print_error(&format_args!("invalid --exclude pattern {}", e), args.flag_quiet);
+            ::std::process::exit(1);
+        },
+    };
+    let indent = match args.flag_indent.as_deref() {
+        Some("tab") => Some("\t".to_string()),
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) => Some(" ".repeat(n)),
+            Err(_) => {
+                print_error(&format_args!("--indent must be a number of spaces or tab, found {}", n), args.flag_quiet);
+                ::std::process::exit(1);
+            },
+        },
+        None => None,
+    };
+    if args.flag_minify && (args.flag_pretty || indent.is_some()) {
+        print_error(&"--minify can't be used with --pretty or --indent", args.flag_quiet);
+        ::std::process::exit(1);
     }
-}
\ No newline at end of file
+    if args.flag_minify && args.flag_trailing_newline == YesNo::Yes {
+        print_error(&"--minify can't be used with --trailing-newline yes", args.flag_quiet);
+        ::std::process::exit(1);
+    }
+    if args.flag_reformat && (args.flag_reverse || args.flag_in_format.is_some()
+        || !matches!(args.flag_output_format, OutputFormat::Json)) {
+        print_error(&"--reformat can't be used with --reverse, --in-format or --output-format", args.flag_quiet);
+        ::std::process::exit(1);
+    }
+    if args.flag_ndjson && (args.flag_reverse || !matches!(args.flag_output_format, OutputFormat::Json)) {
+        print_error(&"--ndjson can't be used with --reverse or --output-format", args.flag_quiet);
+        ::std::process::exit(1);
+    }
+    let opts = Options {
+        direction,
+        pretty: args.flag_pretty || indent.is_some(),
+        indent,
+        minify: args.flag_minify,
+        float_precision: args.flag_float_precision,
+        trailing_newline: args.flag_trailing_newline == YesNo::Yes,
+        depth: args.flag_depth,
+        max_size: args.flag_max_size,
+        strip_components: args.flag_strip_components,
+        exclude,
+        follow_symlinks: args.flag_follow_symlinks,
+        // --log-level wins over --quiet
+        quiet: level == LevelFilter::Error,
+        output_format: args.flag_output_format,
+        force: args.flag_force,
+        jobs: args.flag_jobs,
+        dry_run: args.flag_dry_run,
+        count_only: args.flag_count_only,
+        check: args.flag_check,
+        beside: args.flag_beside,
+        yml: args.flag_yml,
+        out_ext: args.flag_out_ext,
+        comments: args.flag_comments_to,
+        tags: args.flag_tags,
+        front_matter: args.flag_front_matter,
+        skip_unchanged: args.flag_skip_unchanged,
+        retries: args.flag_retries,
+        backup: args.flag_backup,
+        annotate_source: args.flag_annotate_source,
+        stats: args.flag_stats,
+        lock: !args.flag_no_lock,
+        report: args.flag_report,
+        tabular: args.flag_tabular,
+        error_on_empty: args.flag_error_on_empty,
+        case_keys: args.flag_case_keys,
+        stats_tree: args.flag_stats_tree,
+        size_report: args.flag_size_report,
+        yaml_style: args.flag_yaml_style,
+        in_format: if args.flag_reformat { Some(InputFormat::Json) } else { args.flag_in_format },
+        // report every output that is out of date
+        keep_going: args.flag_keep_going || args.flag_check,
+        flatten: args.flag_flatten,
+        flatten_sep: args.flag_flatten_sep,
+        flatten_arrays: args.flag_flatten_arrays,
+        merge_arrays: args.flag_merge_arrays,
+        combine_keyed: args.flag_combine_keyed,
+        select: args.flag_select,
+        sort_keys: args.flag_sort_keys,
+        expand_env: args.flag_expand_env,
+        env_default: args.flag_env_default,
+        decode_binary: args.flag_decode_binary,
+        binary_as: args.flag_binary_as,
+        trim_strings: args.flag_trim_strings,
+        timestamps: args.flag_timestamps,
+        nulls: args.flag_null_as,
+        numbers: args.flag_number_mode,
+        atomic: args.flag_atomic,
+        verify: args.flag_verify,
+        time: args.flag_time,
+        expand_anchors: args.flag_expand_anchors,
+        lint_tabs: args.flag_lint_tabs,
+        strict_keys: args.flag_strict_keys,
+        ndjson: args.flag_ndjson,
+        match_ext: args.flag_match_ext.map(|exts| exts.split(',').map(|ext| ext.trim().to_string()).collect()),
+        template,
+        schema,
+    };
+    let outpath = if args.flag_file {
+        args.arg_inpaths.last().cloned().unwrap_or_default()
+    } else if args.flag_beside {
+        args.arg_inpath.clone()
+    } else {
+        args.arg_outpath.clone()
+    };
+    let res = if args.flag_file && args.flag_in_place {
+        args.arg_inpaths.iter().try_for_each(|path| convert_in_place(path, &opts))
+    } else if args.flag_file && args.arg_inpaths.len() < 2 {
+        print_error(&"<outpath> is required unless using --in-place", opts.quiet);
+        println!("{}", HELP);
+        ::std::process::exit(1);
+    } else if args.flag_file && args.flag_follow {
+        match &args.arg_inpaths[..] {
+            [inpath, _] => convert_follow(inpath, &outpath, &opts),
+            _ => Err(Error::Io("--follow converts a single input".into())),
+        }
+    } else if args.flag_file {
+        let inpaths = &args.arg_inpaths[..args.arg_inpaths.len() - 1];
+        if inpaths.len() == 1 {
+            convert(&inpaths[0], &outpath, &opts)
+        } else {
+            convert_files(inpaths, &outpath, &opts)
+        }
+    } else if args.flag_glob && args.flag_count_only {
+        convert_glob(&args.arg_pattern, &outpath, &opts)
+    } else if args.flag_dir && args.flag_count_only {
+        convert_dir(&args.arg_inpath, &outpath, &opts)
+    } else if args.flag_dir && (args.flag_combine || args.flag_combine_keyed) {
+        combine_dir(&args.arg_inpath, &outpath, &opts)
+    } else if args.flag_dir && args.flag_watch {
+        watch_dir(&args.arg_inpath, &outpath, &opts)
+    } else if args.flag_dir {
+        convert_dir(&args.arg_inpath, &outpath, &opts)
+    } else if args.flag_glob {
+        convert_glob(&args.arg_pattern, &args.arg_outpath, &opts)
+    } else if args.flag_merge {
+        convert_merge(&args.arg_inpaths, &args.arg_outpath, &opts)
+    } else if let Some(list) = &args.flag_from_file {
+        let outpath = Some(args.arg_outpath.as_path()).filter(|path| !path.as_os_str().is_empty());
+        convert_list(list, outpath, &opts)
+    } else { 
+        print_error(&"you must use either the -f, -d, -g, -m or --from-file flag when running", opts.quiet);
+        println!("{}", HELP);
+        ::std::process::exit(1);
+    };
+    match res {
+        Ok(_) => {
+            if opts.dry_run {
+                info!("Dry run complete, no files were written");
+            } else if opts.check {
+                info!("Every output is up to date");
+            } else if !opts.quiet && !opts.count_only && !is_std_stream(&outpath) {
+                info!("Successfully converted your files!")
+            }
+        },
+        Err(e @ Error::NoMatches(_)) => {
+            print_error(&e, opts.quiet);
+            ::std::process::exit(2);
+        },
+        Err(e) => {
+            print_error(&e, opts.quiet);
+            ::std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,451 @@
+//! Passes over a parsed document that run before it is
+//! written in the output format
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_yaml::{Mapping, Value};
+
+use {BinaryAs, Error, KeyCase, NullAs, NumberMode, TagHandling};
+
+/// The text of a mapping key, non-string keys are written
+/// as they would appear in yaml
+pub fn key_name(key: &Value) -> String {
+    match key {
+        Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other)
+            .map(|s| s.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+/// Flatten nested mappings into one mapping, `{a: {b: 1}}`
+/// becomes `{"a.b": 1}` when `sep` is `.`. With `arrays`
+/// sequences are flattened too, using each index as a key.
+/// Anything other than a mapping (or a sequence with `arrays`)
+/// at the top level is returned as is
+pub fn flatten(value: Value, sep: &str, arrays: bool) -> Value {
+    match value {
+        Value::Mapping(_) => (),
+        Value::Sequence(_) if arrays => (),
+        other => return other,
+    }
+    let mut out = Mapping::new();
+    flatten_into(value, None, sep, arrays, &mut out);
+    Value::Mapping(out)
+}
+
+fn flatten_into(value: Value, prefix: Option<String>, sep: &str, arrays: bool, out: &mut Mapping) {
+    let join = |key: String| match &prefix {
+        Some(prefix) => format!("{}{}{}", prefix, sep, key),
+        None => key,
+    };
+    match value {
+        Value::Mapping(map) if !map.is_empty() => {
+            for (k, v) in map {
+                flatten_into(v, Some(join(key_name(&k))), sep, arrays, out);
+            }
+        },
+        Value::Sequence(seq) if arrays && !seq.is_empty() => {
+            for (i, v) in seq.into_iter().enumerate() {
+                flatten_into(v, Some(join(i.to_string())), sep, arrays, out);
+            }
+        },
+        other => {
+            if let Some(key) = prefix {
+                out.insert(Value::String(key), other);
+            }
+        },
+    }
+}
+
+/// Deep merge `overlay` into `base`. Mappings are merged key
+/// by key with `overlay` winning, sequences are replaced unless
+/// `concat_arrays` is set and anything else is replaced
+pub fn merge(base: Value, overlay: Value, concat_arrays: bool) -> Value {
+    match (base, overlay) {
+        (Value::Mapping(mut base), Value::Mapping(overlay)) => {
+            for (k, v) in overlay {
+                let merged = match base.get_mut(&k) {
+                    Some(existing) => merge(::std::mem::replace(existing, Value::Null), v, concat_arrays),
+                    None => v,
+                };
+                base.insert(k, merged);
+            }
+            Value::Mapping(base)
+        },
+        (Value::Sequence(mut base), Value::Sequence(overlay)) if concat_arrays => {
+            base.extend(overlay);
+            Value::Sequence(base)
+        },
+        (_, overlay) => overlay,
+    }
+}
+
+/// Rewrite every number value to match `mode`, mapping keys
+/// are left alone
+pub fn numbers(value: Value, mode: NumberMode) -> Value {
+    match value {
+        Value::Number(n) => match mode {
+            NumberMode::Preserve => Value::Number(n),
+            NumberMode::AllFloats => n.as_f64().map(|f| Value::Number(f.into())).unwrap_or(Value::Number(n)),
+            NumberMode::AllStrings => Value::String(n.to_string()),
+        },
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(|v| numbers(v, mode)).collect()),
+        Value::Mapping(map) => Value::Mapping(map.into_iter().map(|(k, v)| (k, numbers(v, mode))).collect()),
+        Value::Tagged(mut tagged) => {
+            tagged.value = numbers(tagged.value, mode);
+            Value::Tagged(tagged)
+        },
+        other => other,
+    }
+}
+
+/// Round every float to at most `places` decimal places, this
+/// is lossy. Integers, strings and nan or infinite floats are
+/// left alone
+pub fn round_floats(value: Value, places: u32) -> Value {
+    match value {
+        Value::Number(n) if n.is_f64() => match n.as_f64() {
+            Some(f) if f.is_finite() => {
+                let scale = 10f64.powi(places as i32);
+                let rounded = (f * scale).round() / scale;
+                // past this the float has no fractional digits to round
+                Value::Number(if rounded.is_finite() { rounded } else { f }.into())
+            },
+            _ => Value::Number(n),
+        },
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(|v| round_floats(v, places)).collect()),
+        Value::Mapping(map) => Value::Mapping(map.into_iter().map(|(k, v)| (k, round_floats(v, places))).collect()),
+        Value::Tagged(mut tagged) => {
+            tagged.value = round_floats(tagged.value, places);
+            Value::Tagged(tagged)
+        },
+        other => other,
+    }
+}
+
+/// Sort the keys of every mapping, nested ones included
+pub fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Mapping(map) => {
+            let mut entries = map.into_iter()
+                .map(|(k, v)| (k, sort_keys(v)))
+                .collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(::std::cmp::Ordering::Equal));
+            Value::Mapping(entries.into_iter().collect())
+        },
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(sort_keys).collect()),
+        Value::Tagged(mut tagged) => {
+            tagged.value = sort_keys(tagged.value);
+            Value::Tagged(tagged)
+        },
+        other => other,
+    }
+}
+
+/// Rewrite every string mapping key into `case`, nested ones
+/// included. Two keys of the same mapping that end up the same
+/// are an error instead of one replacing the other
+pub fn case_keys(value: Value, case: KeyCase) -> Result<Value, Error> {
+    Ok(match value {
+        Value::Mapping(map) => {
+            let mut out = Mapping::new();
+            for (k, v) in map {
+                let key = match k {
+                    Value::String(s) => Value::String(match case {
+                        KeyCase::Upper => s.to_uppercase(),
+                        KeyCase::Lower => s.to_lowercase(),
+                        KeyCase::None => s,
+                    }),
+                    other => other,
+                };
+                if out.contains_key(&key) {
+                    return Err(Error::Invalid(format!("more than one key becomes {} when changing the case of keys", key_name(&key))));
+                }
+                out.insert(key, case_keys(v, case)?);
+            }
+            Value::Mapping(out)
+        },
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(|v| case_keys(v, case)).collect::<Result<_, _>>()?),
+        Value::Tagged(mut tagged) => {
+            tagged.value = case_keys(tagged.value, case)?;
+            Value::Tagged(tagged)
+        },
+        other => other,
+    })
+}
+
+/// Rewrite null values to match `mode`
+pub fn nulls(value: Value, mode: NullAs) -> Value {
+    match value {
+        Value::Null => match mode {
+            NullAs::EmptyString => Value::String(String::new()),
+            NullAs::Null | NullAs::Omit => Value::Null,
+        },
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(|v| nulls(v, mode)).collect()),
+        Value::Mapping(map) => Value::Mapping(map.into_iter()
+            .filter(|(_, v)| !(v.is_null() && matches!(mode, NullAs::Omit)))
+            .map(|(k, v)| (k, nulls(v, mode)))
+            .collect()),
+        Value::Tagged(mut tagged) => {
+            tagged.value = nulls(tagged.value, mode);
+            Value::Tagged(tagged)
+        },
+        other => other,
+    }
+}
+
+/// Decode every value tagged `!binary`, written as either a
+/// sequence of bytes or normalized base64 depending on `repr`
+pub fn decode_binary(value: Value, repr: BinaryAs) -> Result<Value, Error> {
+    Ok(match value {
+        Value::Tagged(mut tagged) => match (tagged.tag == "binary", tagged.value) {
+            (true, Value::String(text)) => {
+                let text = text.split_whitespace().collect::<String>();
+                let bytes = STANDARD.decode(&text)
+                    .map_err(|e| Error::Invalid(format!("invalid !!binary value: {}", e)))?;
+                match repr {
+                    BinaryAs::Bytes => Value::Sequence(bytes.into_iter().map(|b| Value::Number(b.into())).collect()),
+                    BinaryAs::Base64 => Value::String(STANDARD.encode(bytes)),
+                }
+            },
+            (_, inner) => {
+                tagged.value = decode_binary(inner, repr)?;
+                Value::Tagged(tagged)
+            },
+        },
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter()
+            .map(|v| decode_binary(v, repr))
+            .collect::<Result<_, _>>()?),
+        Value::Mapping(map) => Value::Mapping(map.into_iter()
+            .map(|(k, v)| decode_binary(v, repr).map(|v| (k, v)))
+            .collect::<Result<_, _>>()?),
+        other => other,
+    })
+}
+
+/// The part of `value` found by following the dotted `path`,
+/// segments index into sequences when they are numbers
+pub fn select(value: Value, path: &str) -> Result<Value, Error> {
+    let mut current = value;
+    let mut found = Vec::new();
+    for segment in path.split('.') {
+        // tags are looked through
+        while let Value::Tagged(tagged) = current {
+            current = tagged.value;
+        }
+        let next = match current {
+            Value::Mapping(map) => map.into_iter()
+                .find(|(k, _)| key_name(k) == segment)
+                .map(|(_, v)| v),
+            Value::Sequence(mut seq) => segment.parse::<usize>().ok()
+                .filter(|i| *i < seq.len())
+                .map(|i| seq.swap_remove(i)),
+            _ => None,
+        };
+        current = next.ok_or_else(|| Error::Invalid(format!("--select {}: {} was not found{}", path, segment,
+            if found.is_empty() { String::new() } else { format!(" in {}", found.join(".")) })))?;
+        found.push(segment);
+    }
+    Ok(current)
+}
+
+/// Replace each `${VAR}` in string values with the value of the
+/// environment variable `VAR`. An unset variable is an error
+/// unless there is a `default` to use in its place
+pub fn expand_env(value: Value, default: Option<&str>) -> Result<Value, Error> {
+    Ok(match value {
+        Value::String(s) => Value::String(expand_env_str(&s, default)?),
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter()
+            .map(|v| expand_env(v, default))
+            .collect::<Result<_, _>>()?),
+        Value::Mapping(map) => Value::Mapping(map.into_iter()
+            .map(|(k, v)| expand_env(v, default).map(|v| (k, v)))
+            .collect::<Result<_, _>>()?),
+        Value::Tagged(mut tagged) => {
+            tagged.value = expand_env(tagged.value, default)?;
+            Value::Tagged(tagged)
+        },
+        other => other,
+    })
+}
+
+fn expand_env_str(s: &str, default: Option<&str>) -> Result<String, Error> {
+    let mut ret = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = &rest[start + 2..end];
+        ret.push_str(&rest[..start]);
+        match (::std::env::var(name), default) {
+            (Ok(value), _) => ret.push_str(&value),
+            (Err(_), Some(default)) => ret.push_str(default),
+            (Err(_), None) => return Err(Error::Invalid(format!("environment variable {} is not set", name))),
+        }
+        rest = &rest[end + 1..];
+    }
+    ret.push_str(rest);
+    Ok(ret)
+}
+
+/// Trim the whitespace around every string value. Strings
+/// spanning more than one line, like block scalars, only lose
+/// the trailing whitespace of each line and blank lines at
+/// either end, keeping their indentation and a final newline
+pub fn trim_strings(value: Value) -> Value {
+    match value {
+        Value::String(s) => Value::String(trim_string(&s)),
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(trim_strings).collect()),
+        Value::Mapping(map) => Value::Mapping(map.into_iter().map(|(k, v)| (k, trim_strings(v))).collect()),
+        Value::Tagged(mut tagged) => {
+            tagged.value = trim_strings(tagged.value);
+            Value::Tagged(tagged)
+        },
+        other => other,
+    }
+}
+
+fn trim_string(s: &str) -> String {
+    if !s.trim_end().contains('\n') {
+        return s.trim().to_string();
+    }
+    let lines = s.lines().map(str::trim_end).collect::<Vec<_>>();
+    let start = lines.iter().position(|line| !line.is_empty()).unwrap_or(0);
+    let end = lines.iter().rposition(|line| !line.is_empty()).map(|i| i + 1).unwrap_or(0);
+    let mut ret = lines[start..end.max(start)].join("\n");
+    if s.ends_with('\n') {
+        ret.push('\n');
+    }
+    ret
+}
+
+/// Parse `s` as a yaml 1.1 timestamp, like `2024-01-02` or
+/// `2001-12-14 21:59:43.10 -5`, returning it as an ISO-8601 UTC
+/// timestamp like `2024-01-02T00:00:00Z` or `None` when it
+/// isn't one. Fractional seconds are kept, without trailing zeros
+pub fn timestamp(s: &str) -> Option<String> {
+    fn number(s: &str, min: usize, max: usize) -> Option<i64> {
+        if s.len() < min || s.len() > max || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    }
+    let (date, time) = match s.find(['T', 't', ' ', '\t']) {
+        Some(i) => (&s[..i], Some(s[i + 1..].trim_start())),
+        None => (s, None),
+    };
+    let mut parts = date.split('-');
+    let year = number(parts.next()?, 4, 4)?;
+    let (month, day) = match (parts.next(), parts.next(), parts.next(), time) {
+        // a date on its own needs two digit months and days
+        (Some(month), Some(day), None, None) => (number(month, 2, 2)?, number(day, 2, 2)?),
+        (Some(month), Some(day), None, Some(_)) => (number(month, 1, 2)?, number(day, 1, 2)?),
+        _ => return None,
+    };
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    let (mut seconds, fraction) = match time {
+        None => (0, ""),
+        Some(time) => {
+            let zone_at = time.find(['Z', '+', '-', ' ', '\t']).unwrap_or(time.len());
+            let (clock, zone) = time.split_at(zone_at);
+            let (clock, fraction) = match clock.find('.') {
+                Some(i) => (&clock[..i], clock[i + 1..].trim_end_matches('0')),
+                None => (clock, ""),
+            };
+            if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let mut clock = clock.split(':');
+            let hour = number(clock.next()?, 1, 2)?;
+            let minute = number(clock.next()?, 2, 2)?;
+            let second = number(clock.next()?, 2, 2)?;
+            if clock.next().is_some() || hour > 23 || minute > 59 || second > 59 {
+                return None;
+            }
+            let offset = match zone.trim_start() {
+                "" | "Z" => 0,
+                zone => {
+                    let sign = if zone.starts_with('-') { -1 } else if zone.starts_with('+') { 1 } else { return None };
+                    let mut zone = zone[1..].splitn(2, ':');
+                    let hours = number(zone.next()?, 1, 2)?;
+                    let minutes = match zone.next() {
+                        Some(minutes) => number(minutes, 2, 2)?,
+                        None => 0,
+                    };
+                    sign * (hours * 3600 + minutes * 60)
+                },
+            };
+            (hour * 3600 + minute * 60 + second - offset, fraction)
+        },
+    };
+    seconds += days_from_civil(year, month, day) * 86400;
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let seconds = seconds.rem_euclid(86400);
+    let mut ret = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year, month, day, seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    if !fraction.is_empty() {
+        ret.push('.');
+        ret.push_str(fraction);
+    }
+    ret.push('Z');
+    Some(ret)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The number of days from 1970-01-01 to a date, see
+/// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date `days` after 1970-01-01, the inverse of
+/// `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+/// Strip, wrap or reject every tagged value, see `TagHandling`.
+/// Nested tags are handled the same way
+pub fn tags(value: Value, mode: TagHandling) -> Result<Value, Error> {
+    Ok(match value {
+        Value::Sequence(seq) => Value::Sequence(seq.into_iter().map(|v| tags(v, mode)).collect::<Result<_, _>>()?),
+        Value::Mapping(map) => Value::Mapping(map.into_iter()
+            .map(|(k, v)| tags(v, mode).map(|v| (k, v)))
+            .collect::<Result<_, _>>()?),
+        Value::Tagged(tagged) => match mode {
+            TagHandling::Strip => tags(tagged.value, mode)?,
+            TagHandling::Wrap => {
+                let mut wrapped = Mapping::new();
+                wrapped.insert(Value::String("__tag__".into()), Value::String(tagged.tag.to_string()));
+                wrapped.insert(Value::String("value".into()), tags(tagged.value, mode)?);
+                Value::Mapping(wrapped)
+            },
+            TagHandling::Error => return Err(Error::Invalid(format!("found the yaml tag {}, use --tags strip or wrap to convert it", tagged.tag))),
+        },
+        other => other,
+    })
+}
@@ -0,0 +1,408 @@
+extern crate json5;
+extern crate serde_json;
+extern crate tempfile;
+extern crate y2j;
+
+use std::fs::{create_dir_all, read_to_string, write};
+use std::path::PathBuf;
+#[cfg(feature = "dir")]
+use std::fs::create_dir;
+
+use y2j::{convert, convert_files, from_yaml, yaml_aliases, Direction, Error, InputFormat, KeyCase, Options, OutputFormat, YamlStyle};
+#[cfg(feature = "dir")]
+use y2j::{combine_dir, convert_dir};
+
+fn quiet() -> Options {
+    Options {
+        quiet: true,
+        ..Options::default()
+    }
+}
+
+/// A temp dir with `yaml` written to its `in.yaml`, along with
+/// the paths of that file and an `out.json` beside it
+fn temp_input(yaml: &str) -> (tempfile::TempDir, PathBuf, PathBuf) {
+    let dir = tempfile::tempdir().unwrap();
+    let from = dir.path().join("in.yaml");
+    let to = dir.path().join("out.json");
+    write(&from, yaml).unwrap();
+    (dir, from, to)
+}
+
+/// Convert `yaml` by way of a temp file, returning the json
+fn convert_str(yaml: &str, opts: &Options) -> String {
+    let (_dir, from, to) = temp_input(yaml);
+    convert(&from, &to, opts).unwrap();
+    read_to_string(&to).unwrap()
+}
+
+/// Convert `yaml` by way of a temp file, expecting it to fail
+/// without writing anything. Returns the error and the input's
+/// path, which messages start with
+fn convert_err(yaml: &str, opts: &Options) -> (Error, PathBuf) {
+    let (_dir, from, to) = temp_input(yaml);
+    let e = convert(&from, &to, opts).expect_err("expected the conversion to fail");
+    assert!(!to.exists());
+    (e, from)
+}
+
+#[test]
+fn mapping() {
+    assert_eq!(convert_str("a: 1\nb: [x, y]\n", &quiet()), r#"{"a":1,"b":["x","y"]}"#);
+}
+
+#[test]
+fn malformed_yaml() {
+    match convert_err("a: [1, 2\n", &quiet()) {
+        (Error::DeError(msg), from) => {
+            assert!(msg.starts_with(&format!("{}:2:1: did not find expected", from.display())), "{}", msg);
+            // the location is only given once
+            assert!(!msg.contains("at line 2 column 1"), "{}", msg);
+        },
+        (other, _) => panic!("expected a DeError, found {:?}", other),
+    }
+}
+
+#[test]
+fn top_level_sequence() {
+    assert_eq!(convert_str("- a\n- b\n", &quiet()), r#"["a","b"]"#);
+}
+
+#[test]
+fn sequence_of_maps() {
+    let yaml = "- name: a\n  tags: [x, y]\n- name: b\n  nested:\n    value: 1\n";
+    assert_eq!(
+        convert_str(yaml, &quiet()),
+        r#"[{"name":"a","tags":["x","y"]},{"name":"b","nested":{"value":1}}]"#
+    );
+}
+
+#[cfg(feature = "dir")]
+#[test]
+fn top_level_sequence_in_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let from = dir.path().join("in");
+    let to = dir.path().join("out");
+    create_dir(&from).unwrap();
+    create_dir(&to).unwrap();
+    write(from.join("records.yaml"), "- id: 1\n- id: 2\n").unwrap();
+    convert_dir(&from, &to, &quiet()).unwrap();
+    let json = read_to_string(to.join("records.json")).unwrap();
+    assert_eq!(json, r#"[{"id":1},{"id":2}]"#);
+}
+
+#[test]
+fn anchors_are_expanded() {
+    let yaml = "base: &base\n  name: a\n  tags: [x]\ncopy: *base\nlist: [&v 1, *v]\n";
+    assert_eq!(
+        convert_str(yaml, &quiet()),
+        r#"{"base":{"name":"a","tags":["x"]},"copy":{"name":"a","tags":["x"]},"list":[1,1]}"#
+    );
+}
+
+#[test]
+fn aliases_are_found() {
+    let yaml = "base: &base\n  math: 2 * 3\ncopy: *base # *not\nquoted: \"*no\"\nlist: [&v 1, *v]\n";
+    assert_eq!(
+        yaml_aliases(yaml),
+        vec![(3, "base".to_string()), (5, "v".to_string())]
+    );
+}
+
+#[test]
+fn merge_keys() {
+    let yaml = "base: &base\n  image: rust\n  script: test\njob:\n  <<: *base\n  script: build\n";
+    assert_eq!(
+        convert_str(yaml, &quiet()),
+        r#"{"base":{"image":"rust","script":"test"},"job":{"script":"build","image":"rust"}}"#
+    );
+}
+
+#[test]
+fn merge_key_sequence() {
+    let yaml = "a: &a\n  x: 1\nb: &b\n  x: 2\n  y: 2\nc:\n  <<: [*a, *b]\n";
+    assert_eq!(
+        convert_str(yaml, &quiet()),
+        r#"{"a":{"x":1},"b":{"x":2,"y":2},"c":{"x":1,"y":2}}"#
+    );
+}
+
+#[test]
+fn timestamps() {
+    let opts = Options {
+        timestamps: true,
+        ..quiet()
+    };
+    let yaml = "date: 2024-01-02\ntagged: !!timestamp 2024-01-02\nutc: 2024-01-02T03:04:05Z\n\
+        spaced: 2001-12-14 21:59:43.10 -5\noffset: 2001-12-31t23:30:00+01:30\nnot: 2024-13-02\n\
+        quoted: \"2024-01-02\"\nsingle: '2024-01-02'\ntagged_quoted: !!timestamp \"2024-01-02\"\n2024-01-02: key\n\
+        str: !!str 2024-01-02\nanchored: &d 2024-01-02\nflow: [2024-01-02, \"2024-01-02\", !!str 2024-01-02, !!timestamp 2024-01-02]\n";
+    assert_eq!(
+        convert_str(yaml, &opts),
+        r#"{"date":"2024-01-02T00:00:00Z","tagged":"2024-01-02T00:00:00Z","utc":"2024-01-02T03:04:05Z","spaced":"2001-12-15T02:59:43.1Z","offset":"2001-12-31T22:00:00Z","not":"2024-13-02","quoted":"2024-01-02","single":"2024-01-02","tagged_quoted":"2024-01-02T00:00:00Z","2024-01-02":"key","str":"2024-01-02","anchored":"2024-01-02T00:00:00Z","flow":["2024-01-02T00:00:00Z","2024-01-02","2024-01-02","2024-01-02T00:00:00Z"]}"#
+    );
+}
+
+#[test]
+fn binary_tags() {
+    let opts = Options {
+        decode_binary: true,
+        ..quiet()
+    };
+    let yaml = "doc: |\n  use !!binary for bytes\n  !!binary\nplain: use !!binary here\nquoted: \"!!binary aGk=\"\n\
+        list:\n  - !!binary aGk=\n  - |\n    !!binary\n  - [!!binary aGk=]\nblock: !!binary |\n  aGk=\nafter: !!binary aGk=\n\
+        multi: \"first line\n  - !!binary aGk= \\\" still quoted\"\nsingle: 'it''s\n  b: !!binary aGk='\n";
+    assert_eq!(
+        convert_str(yaml, &opts),
+        r#"{"doc":"use !!binary for bytes\n!!binary\n","plain":"use !!binary here","quoted":"!!binary aGk=","list":[[104,105],"!!binary\n",[[104,105]]],"block":[104,105],"after":[104,105],"multi":"first line - !!binary aGk= \" still quoted","single":"it's b: !!binary aGk="}"#
+    );
+}
+
+#[test]
+fn files_onto_themselves() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.yaml");
+    let b = dir.path().join("b.yaml");
+    write(&a, "a: 1\n").unwrap();
+    write(&b, "b: 2\n").unwrap();
+    let opts = Options {
+        force: true,
+        out_ext: Some("yaml".into()),
+        ..quiet()
+    };
+    assert!(convert_files(&[a.clone(), b.clone()], dir.path(), &opts).is_err());
+    assert_eq!(read_to_string(&a).unwrap(), "a: 1\n");
+    assert_eq!(read_to_string(&b).unwrap(), "b: 2\n");
+}
+
+#[test]
+fn failed_atomic_write_keeps_output() {
+    let (dir, from, to) = temp_input("a: 1\n");
+    write(&to, "old").unwrap();
+    // the temp file can't be created over a directory
+    create_dir_all(dir.path().join(".out.json.y2j-tmp")).unwrap();
+    let opts = Options {
+        force: true,
+        backup: true,
+        atomic: true,
+        ..quiet()
+    };
+    assert!(convert(&from, &to, &opts).is_err());
+    assert_eq!(read_to_string(&to).unwrap(), "old");
+    assert_eq!(read_to_string(dir.path().join("out.json.bak")).unwrap(), "old");
+}
+
+#[test]
+fn tabular_with_annotations() {
+    let opts = Options {
+        tabular: true,
+        annotate_source: true,
+        ..quiet()
+    };
+    let json = convert_str("- a: 1\n- a: 2\n", &opts);
+    assert!(json.ends_with(r#""data":[{"a":1},{"a":2}]}"#), "{}", json);
+}
+
+#[cfg(feature = "dir")]
+#[test]
+fn tabular_combined() {
+    let dir = tempfile::tempdir().unwrap();
+    let from = dir.path().join("in");
+    let to = dir.path().join("all.json");
+    create_dir(&from).unwrap();
+    write(from.join("a.yaml"), "- id: 1\n").unwrap();
+    write(from.join("b.yaml"), "- id: 2\n").unwrap();
+    let opts = Options {
+        tabular: true,
+        ..quiet()
+    };
+    combine_dir(&from, &to, &opts).unwrap();
+    assert_eq!(read_to_string(&to).unwrap(), r#"[[{"id":1}],[{"id":2}]]"#);
+}
+
+#[test]
+fn minify_keeps_floats() {
+    let opts = Options {
+        minify: true,
+        output_format: OutputFormat::Toml,
+        ..quiet()
+    };
+    assert_eq!(convert_str("a: 1.0\n", &opts).trim_end(), "a = 1.0");
+    let opts = Options {
+        minify: true,
+        ..quiet()
+    };
+    assert_eq!(convert_str("a: 1.0\nb: 1\nc: 1.50\n", &opts), r#"{"a":1.0,"b":1,"c":1.5}"#);
+}
+
+#[cfg(feature = "dir")]
+#[test]
+fn lock_new_output_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let from = dir.path().join("in");
+    let to = dir.path().join("new").join("out");
+    create_dir(&from).unwrap();
+    write(from.join("a.yaml"), "a: 1\n").unwrap();
+    let opts = Options {
+        lock: true,
+        ..quiet()
+    };
+    convert_dir(&from, &to, &opts).unwrap();
+    assert_eq!(read_to_string(to.join("a.json")).unwrap(), r#"{"a":1}"#);
+    // the lock file goes away with the lock
+    assert!(!to.join(".y2j.lock").exists());
+    let opts = Options {
+        lock: true,
+        beside: true,
+        ..quiet()
+    };
+    convert_dir(&from, &from, &opts).unwrap();
+    assert!(from.join("a.json").exists());
+    assert!(!from.join(".y2j.lock").exists());
+}
+
+#[test]
+fn duplicate_key_location() {
+    let opts = Options {
+        strict_keys: true,
+        ..quiet()
+    };
+    match convert_err("x: 0\ny:\n  z: 1\n  w: 2\n  z: 3\n", &opts) {
+        (Error::DeError(msg), from) => assert!(msg.starts_with(&format!("{}:5:3: y: duplicate key z", from.display())), "{}", msg),
+        (other, _) => panic!("expected a DeError, found {:?}", other),
+    }
+}
+
+#[test]
+fn tab_indentation() {
+    match convert_err("a:\n\tb: 1\n", &quiet()) {
+        (Error::DeError(msg), _) => assert!(msg.ends_with(":2:1: a tab is used for indentation, yaml only allows spaces"), "{}", msg),
+        (other, _) => panic!("expected a DeError, found {:?}", other),
+    }
+}
+
+#[test]
+fn yaml_flow_round_trip() {
+    let opts = Options {
+        direction: Direction::JsonToYaml,
+        in_format: Some(InputFormat::Yaml),
+        yaml_style: YamlStyle::Flow,
+        ..quiet()
+    };
+    let yaml = "commas: a, b\nbrackets: \"[a]\"\nbraces: \"{a}\"\ncolon: \"a: b\"\nstarts: \":x\"\nquestion: \"?x\"\nurl: http://x\nhash: \"a #b\"\n\
+        mixed: \"x,[y]:{z}#\"\nlines: \"a\\nb\"\nempty: ''\nseq: []\nmap: {}\nnested: {a: [1, {b: null}]}\n\
+        tagged: !Foo {a: 1}\ntagged_seq: !Bar [1, 2]\ntagged_str: !Baz a, b\n\"a, b\": key\n";
+    let flow = convert_str(yaml, &opts);
+    assert_eq!(flow.lines().count(), 1, "{}", flow);
+    assert_eq!(from_yaml(&flow).unwrap(), from_yaml(yaml).unwrap(), "{}", flow);
+}
+
+#[test]
+fn json5_round_trip() {
+    let opts = Options {
+        output_format: OutputFormat::Json5,
+        ..quiet()
+    };
+    let yaml = "commas: a, b\nbrackets: \"[a]\"\nbraces: \"{a}\"\ncolon: \"a: b\"\nhash: \"a #b\"\n\
+        quote: \"a \\\"b\\\"\"\nlines: \"a\\nb\"\nseq: []\nmap: {}\nnested: {a: [1, {b: null}]}\n\
+        tagged: !Foo {a: 1}\n\"a, b\": key\n_ident$1: x\n1key: y\n";
+    let json5: serde_json::Value = json5::from_str(&convert_str(yaml, &opts)).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&convert_str(yaml, &quiet())).unwrap();
+    assert_eq!(json5, json);
+}
+
+#[test]
+fn json5_non_finite() {
+    let opts = Options {
+        output_format: OutputFormat::Json5,
+        ..quiet()
+    };
+    let json5 = convert_str("nan: .nan\ninf: .inf\nneg: -.inf\n", &opts);
+    assert_eq!(json5, "{\n  nan: NaN,\n  inf: Infinity,\n  neg: -Infinity,\n}");
+    let parsed: ::std::collections::BTreeMap<String, f64> = json5::from_str(&json5).unwrap();
+    assert!(parsed["nan"].is_nan());
+    assert_eq!(parsed["inf"], f64::INFINITY);
+    assert_eq!(parsed["neg"], f64::NEG_INFINITY);
+}
+
+#[test]
+fn tab_in_block_scalar() {
+    let opts = Options {
+        lint_tabs: true,
+        ..quiet()
+    };
+    assert_eq!(convert_str("text: |\n  line\n  \tok\n", &opts), r#"{"text":"line\n\tok\n"}"#);
+    match convert_err("text: |\n  line\n  \tok\nother: ok\nbad: [1, 2\n", &quiet()) {
+        (Error::DeError(msg), _) => assert!(msg.contains(":6:1: did not find expected ',' or ']'"), "{}", msg),
+        (other, _) => panic!("expected a DeError, found {:?}", other),
+    }
+}
+
+#[test]
+fn flatten_keeps_empty_mappings() {
+    let opts = Options {
+        flatten: true,
+        flatten_sep: ".".into(),
+        ..quiet()
+    };
+    assert_eq!(convert_str("a: {}\nb:\n  c: 1\n  d: [1, 2]\n  e: {}\n", &opts), r#"{"a":{},"b.c":1,"b.d":[1,2],"b.e":{}}"#);
+}
+
+#[test]
+fn flatten_arrays() {
+    let opts = Options {
+        flatten: true,
+        flatten_sep: "_".into(),
+        flatten_arrays: true,
+        ..quiet()
+    };
+    assert_eq!(convert_str("a:\n  - x\n  - b: 1\n  - []\n", &opts), r#"{"a_0":"x","a_1_b":1,"a_2":[]}"#);
+    assert_eq!(convert_str("- 1\n- [2]\n", &opts), r#"{"0":1,"1_0":2}"#);
+}
+
+#[test]
+fn expand_env() {
+    std::env::set_var("Y2J_TEST_EXPAND", "set");
+    let opts = Options {
+        expand_env: true,
+        ..quiet()
+    };
+    // an unterminated `${` is left as written
+    assert_eq!(convert_str("a: ${Y2J_TEST_EXPAND}-${Y2J_TEST_EXPAND\n", &opts), r#"{"a":"set-${Y2J_TEST_EXPAND"}"#);
+    match convert_err("a: ${Y2J_TEST_UNSET}\n", &opts) {
+        (Error::Invalid(msg), _) => assert!(msg.contains("environment variable Y2J_TEST_UNSET is not set"), "{}", msg),
+        (other, _) => panic!("expected an Invalid, found {:?}", other),
+    }
+    let opts = Options {
+        env_default: Some("none".into()),
+        ..opts
+    };
+    assert_eq!(convert_str("a: [\"${Y2J_TEST_UNSET}\", \"${Y2J_TEST_EXPAND}\"]\n", &opts), r#"{"a":["none","set"]}"#);
+}
+
+#[test]
+fn trim_strings() {
+    let opts = Options {
+        trim_strings: true,
+        ..quiet()
+    };
+    assert_eq!(convert_str("a: '  x  '\nb: \"y \\n\"\n", &opts), r#"{"a":"x","b":"y"}"#);
+    // block scalars keep their indentation and final newline
+    assert_eq!(convert_str("a: |\n  first   \n    indented  \n", &opts), r#"{"a":"first\n  indented\n"}"#);
+    // but lose blank lines at either end
+    assert_eq!(convert_str("a: |+\n\n  x  \n  y\n\n\nb: 1\n", &opts), r#"{"a":"x\ny\n","b":1}"#);
+    assert_eq!(convert_str("a: \"\\n x\\n\\ty \"\n", &opts), r#"{"a":" x\n\ty"}"#);
+}
+
+#[test]
+fn case_keys() {
+    let opts = Options {
+        case_keys: KeyCase::Lower,
+        ..quiet()
+    };
+    assert_eq!(convert_str("Name: x\nInner: {Key: 1}\n", &opts), r#"{"name":"x","inner":{"key":1}}"#);
+    match convert_err("a:\n  Key: 1\n  KEY: 2\n", &opts) {
+        (Error::Invalid(msg), _) => assert!(msg.contains("more than one key becomes key when changing the case of keys"), "{}", msg),
+        (other, _) => panic!("expected an Invalid, found {:?}", other),
+    }
+}
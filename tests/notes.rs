@@ -0,0 +1,26 @@
+extern crate y2j;
+
+use y2j::Notes;
+
+#[test]
+fn nested_notes() {
+    let yaml = "title: a\nnotes:\n  - title: b\n    notes:\n      - title: c\n";
+    let notes = Notes::from_yaml(yaml).unwrap();
+    assert_eq!(
+        notes.to_json().unwrap(),
+        r#"{"title":"a","notes":[{"title":"b","notes":[{"title":"c","notes":null}]}]}"#
+    );
+}
+
+#[test]
+fn missing_notes() {
+    let notes = Notes::from_yaml("title: a\n").unwrap();
+    assert_eq!(notes.title, "a");
+    assert!(notes.notes.is_none());
+    assert_eq!(notes.to_json().unwrap(), r#"{"title":"a","notes":null}"#);
+}
+
+#[test]
+fn missing_title() {
+    assert!(Notes::from_yaml("notes: []\n").is_err());
+}